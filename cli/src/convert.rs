@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Result, load_trace, save_trace};
+use clap::ValueEnum;
+use common::{FoldedWeight, read_chrome_trace, write_chrome_trace, write_folded};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Trace to convert
+    input: PathBuf,
+    /// Where to write the result
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Format to read, chrome-json for .json files and racy otherwise if left
+    /// out
+    #[arg(long, value_enum)]
+    from: Option<InputFormat>,
+    /// Format to write
+    #[arg(short, long, alias = "format", value_enum, default_value_t = Format::Racy)]
+    to: Format,
+    /// Time each folded stack is weighted by
+    #[arg(long, value_enum, default_value_t = Weight::SelfTime)]
+    weight: Weight,
+    /// Whether folded stacks of different threads are kept apart, under a
+    /// frame naming the thread, or merged
+    #[arg(long, value_enum, default_value_t = Threads::Merge)]
+    threads: Threads,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    Racy,
+    ChromeJson,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// The current racy format, e.g. to upgrade files written before the
+    /// header or string table existed
+    Racy,
+    /// Trace Event Format JSON, for chrome://tracing and ui.perfetto.dev
+    ChromeJson,
+    /// Folded stacks, for flamegraph.pl and inferno; can't be read back
+    Folded,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Weight {
+    /// Time spent in the innermost span itself
+    #[value(name = "self")]
+    SelfTime,
+    /// Whole duration of the innermost span
+    Total,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Threads {
+    Keep,
+    Merge,
+}
+
+impl InputFormat {
+    fn of(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            InputFormat::ChromeJson
+        } else {
+            InputFormat::Racy
+        }
+    }
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let trace = match args.from.unwrap_or_else(|| InputFormat::of(&args.input)) {
+        InputFormat::Racy => load_trace(args.input)?,
+        InputFormat::ChromeJson => read_chrome_trace(args.input.clone())
+            .map_err(|err| format!("{}: {err}", args.input.display()))?,
+    };
+    let written = match args.to {
+        Format::Racy => return save_trace(args.output, &trace),
+        Format::ChromeJson => write_chrome_trace(args.output.clone(), &trace),
+        Format::Folded => {
+            let weight = match args.weight {
+                Weight::SelfTime => FoldedWeight::SelfTime,
+                Weight::Total => FoldedWeight::Total,
+            };
+            let per_thread = args.threads == Threads::Keep;
+            write_folded(args.output.clone(), &trace, weight, per_thread)
+        }
+    };
+    written.map_err(|err| format!("{}: {err}", args.output.display()).into())
+}
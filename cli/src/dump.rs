@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use common::{Arg, ClockSource, FlowPhase, Trace};
+
+use crate::{Result, input_path, load_trace, print_report};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Trace to print
+    file: Option<PathBuf>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let trace = load_trace(input_path(args.file)?)?;
+    print_report(&dump(&trace))
+}
+
+/// One line per record, timestamps in nanoseconds since the recording started.
+fn dump(trace: &Trace) -> String {
+    let header = &trace.header;
+    let since_start = |timestamp: u128| timestamp.saturating_sub(header.start_time);
+    let clock = match header.clock {
+        ClockSource::Realtime => "realtime",
+        ClockSource::Monotonic => "monotonic",
+    };
+    let mut out = format!(
+        "header   version={} pid={} exe={} clock={} start={}\n",
+        header.version, header.pid, header.exe_name, clock, header.start_time
+    );
+
+    for process in &trace.processes {
+        out += &format!(
+            "process  pid={} exe={} cmd={}\n",
+            process.pid,
+            process.exe_name,
+            process.command_line.join(" ")
+        );
+    }
+    for thread in &trace.threads {
+        out += &format!("thread   {}/{} {}\n", thread.pid, thread.id, thread.name);
+    }
+    for event in &trace.events {
+        out += &format!(
+            "span     {}/{} t={} dur={} id={} parent={} depth={} {}",
+            event.pid,
+            event.id,
+            since_start(event.timestamp),
+            event.duration,
+            event.span_id,
+            event.parent_id,
+            event.depth,
+            event.name
+        );
+        if let Some(category) = &event.category {
+            out += &format!(" [{category}]");
+        }
+        if event.task_id != 0 {
+            out += &format!(" task={}", event.task_id);
+        }
+        if let Some(location) = &event.location {
+            out += &format!(" at {}:{}", location.file, location.line);
+        }
+        out += &format_args(&event.args);
+        out += "\n";
+    }
+    for mark in &trace.marks {
+        out += &format!(
+            "mark     {}/{} t={} scope={:?} {}{}\n",
+            mark.pid,
+            mark.id,
+            since_start(mark.timestamp),
+            mark.scope,
+            mark.name,
+            format_args(&mark.args)
+        );
+    }
+    for counter in &trace.counters {
+        out += &format!(
+            "counter  {}/{} t={} {}={}\n",
+            counter.pid,
+            counter.id,
+            since_start(counter.timestamp),
+            counter.name,
+            counter.value
+        );
+    }
+    for flow in &trace.flows {
+        let phase = match flow.phase {
+            FlowPhase::Begin => "begin",
+            FlowPhase::End => "end",
+        };
+        out += &format!(
+            "flow     {}/{} t={} {} id={} span={}\n",
+            flow.pid,
+            flow.thread,
+            since_start(flow.timestamp),
+            phase,
+            flow.id,
+            flow.span_id
+        );
+    }
+    out
+}
+
+fn format_args(args: &[Arg]) -> String {
+    args.iter()
+        .map(|arg| format!(" {}={}", arg.key, arg.value))
+        .collect()
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use common::{Event, Trace};
+
+use crate::{Result, load_trace, save_trace};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Trace to filter
+    input: PathBuf,
+    /// Where to write the records that are kept
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Keep spans whose name contains this text
+    #[arg(long)]
+    name: Option<String>,
+    /// Keep records of this process
+    #[arg(long)]
+    pid: Option<u32>,
+    /// Keep records of this thread id
+    #[arg(long)]
+    thread: Option<u64>,
+    /// Keep spans lasting at least this many nanoseconds
+    #[arg(long, value_name = "NANOS")]
+    min_duration: Option<u64>,
+    /// Drop records that end this many nanoseconds into the trace or earlier
+    #[arg(long, value_name = "NANOS")]
+    from: Option<u64>,
+    /// Drop records that start this many nanoseconds into the trace or later
+    #[arg(long, value_name = "NANOS")]
+    to: Option<u64>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let mut trace = load_trace(args.input.clone())?;
+    filter(&mut trace, &args);
+    save_trace(args.output, &trace)
+}
+
+impl Args {
+    /// Whether a record of `thread` in `pid` spanning `start..end` is kept,
+    /// whatever kind of record it is.
+    fn keeps(&self, pid: u32, thread: u64, start: u64, end: u64) -> bool {
+        self.pid.is_none_or(|wanted| wanted == pid)
+            && self.thread.is_none_or(|wanted| wanted == thread)
+            && self.from.is_none_or(|from| end > from)
+            && self.to.is_none_or(|to| start < to)
+    }
+
+    fn keeps_span(&self, event: &Event, start: u64) -> bool {
+        self.keeps(event.pid, event.id, start, start + event.duration)
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| event.name.contains(name))
+            && self.min_duration.is_none_or(|min| event.duration >= min)
+    }
+}
+
+fn filter(trace: &mut Trace, args: &Args) {
+    let origin = trace
+        .events
+        .iter()
+        .map(|event| event.timestamp)
+        .chain(trace.marks.iter().map(|mark| mark.timestamp))
+        .chain(trace.counters.iter().map(|counter| counter.timestamp))
+        .min()
+        .unwrap_or(0);
+    let offset = |timestamp: u128| timestamp.saturating_sub(origin) as u64;
+
+    let parents: HashMap<(u32, u64), u64> = trace
+        .events
+        .iter()
+        .filter(|event| event.span_id != 0)
+        .map(|event| ((event.pid, event.span_id), event.parent_id))
+        .collect();
+    trace
+        .events
+        .retain(|event| args.keeps_span(event, offset(event.timestamp)));
+    relink(&mut trace.events, &parents);
+
+    trace.marks.retain(|mark| {
+        let at = offset(mark.timestamp);
+        args.keeps(mark.pid, mark.id, at, at)
+    });
+    trace.counters.retain(|counter| {
+        let at = offset(counter.timestamp);
+        args.keeps(counter.pid, counter.id, at, at)
+    });
+
+    // A flow is only drawn between spans that are both still there. Ends
+    // recorded outside any span have no span to lose.
+    let spans: HashSet<(u32, u64)> = trace
+        .events
+        .iter()
+        .map(|event| (event.pid, event.span_id))
+        .collect();
+    trace.flows.retain(|flow| {
+        let at = offset(flow.timestamp);
+        args.keeps(flow.pid, flow.thread, at, at)
+            && (flow.span_id == 0 || spans.contains(&(flow.pid, flow.span_id)))
+    });
+
+    trace
+        .threads
+        .retain(|thread| args.keeps(thread.pid, thread.id, 0, u64::MAX));
+    trace
+        .processes
+        .retain(|process| args.pid.is_none_or(|pid| pid == process.pid));
+}
+
+/// Points every span at its closest ancestor that was kept, fixing depths to
+/// match, so the call tree stays whole.
+fn relink(events: &mut [Event], parents: &HashMap<(u32, u64), u64>) {
+    let kept: HashSet<(u32, u64)> = events
+        .iter()
+        .map(|event| (event.pid, event.span_id))
+        .collect();
+
+    // Parents start no later than their children and last at least as long,
+    // so they come first in this order.
+    events.sort_by(|a, b| {
+        a.timestamp
+            .cmp(&b.timestamp)
+            .then(b.duration.cmp(&a.duration))
+    });
+    let mut depths: HashMap<(u32, u64), u32> = HashMap::new();
+    for event in events.iter_mut().filter(|event| event.span_id != 0) {
+        let mut parent = event.parent_id;
+        while parent != 0 && !kept.contains(&(event.pid, parent)) {
+            parent = parents.get(&(event.pid, parent)).copied().unwrap_or(0);
+        }
+        event.parent_id = parent;
+        event.depth = match depths.get(&(event.pid, parent)) {
+            Some(depth) => depth + 1,
+            None => 0,
+        };
+        depths.insert((event.pid, event.span_id), event.depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{ClockSource, Flow, FlowPhase, Header};
+
+    fn args() -> Args {
+        Args {
+            input: PathBuf::new(),
+            output: PathBuf::new(),
+            name: None,
+            pid: None,
+            thread: None,
+            min_duration: None,
+            from: None,
+            to: None,
+        }
+    }
+
+    /// Ends of the flows left after filtering, as (thread, span id).
+    fn flows_kept(args: &Args) -> Vec<(u64, u64)> {
+        let flow = |thread: u64, span_id: u64| Flow {
+            id: 1,
+            phase: FlowPhase::Begin,
+            pid: 1,
+            thread,
+            span_id,
+            timestamp: 5,
+        };
+        let mut trace = Trace {
+            events: vec![Event {
+                pid: 1,
+                id: 1,
+                span_id: 1,
+                duration: 10,
+                name: "work".to_string(),
+                ..Default::default()
+            }],
+            flows: vec![flow(1, 1), flow(1, 0), flow(2, 0)],
+            ..Trace::new(Header::current(ClockSource::Realtime))
+        };
+        filter(&mut trace, args);
+        trace
+            .flows
+            .iter()
+            .map(|flow| (flow.thread, flow.span_id))
+            .collect()
+    }
+
+    #[test]
+    fn flows_go_through_the_same_criteria() {
+        assert_eq!(flows_kept(&args()), [(1, 1), (1, 0), (2, 0)]);
+        let thread = Args {
+            thread: Some(1),
+            ..args()
+        };
+        assert_eq!(flows_kept(&thread), [(1, 1), (1, 0)]);
+        // Only the flow inside the dropped span goes with it.
+        let name = Args {
+            name: Some("other".to_string()),
+            ..args()
+        };
+        assert_eq!(flows_kept(&name), [(1, 0), (2, 0)]);
+    }
+}
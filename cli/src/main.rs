@@ -1,14 +1,107 @@
-use std::{env, path::PathBuf};
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
 
-use common::{default_save_filename, read_events};
+use clap::{Parser, Subcommand};
+use common::{Event, Trace, latest_save_filename, read_events, read_trace, write_trace};
 
-pub fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filepath: PathBuf = if args.len() > 1 {
-        args[1].parse().unwrap()
-    } else {
-        default_save_filename()
+mod convert;
+mod dump;
+mod filter;
+mod merge;
+mod stats;
+mod validate;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Inspects and transforms racy trace files.
+///
+/// Commands taking an optional FILE read the newest trace in the output
+/// directory (see RACY_OUTPUT) when it is left out.
+#[derive(Parser)]
+#[command(name = "racy-cli", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print every record of a trace, one per line
+    Dump(dump::Args),
+    /// Print call counts and timings per span name
+    Stats(stats::Args),
+    /// Rewrite a trace in another format
+    Convert(convert::Args),
+    /// Combine traces of several processes into one
+    Merge(merge::Args),
+    /// Keep only the records matching some criteria
+    Filter(filter::Args),
+    /// Check that traces are readable and consistent
+    Validate(validate::Args),
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Dump(args) => dump::run(args),
+        Command::Stats(args) => stats::run(args),
+        Command::Convert(args) => convert::run(args),
+        Command::Merge(args) => merge::run(args),
+        Command::Filter(args) => filter::run(args),
+        Command::Validate(args) => validate::run(args),
     };
-    let events = read_events(filepath).unwrap();
-    println!("{events:?}");
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("racy-cli: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `file`, or the newest trace in the output directory if it is `None`.
+fn input_path(file: Option<PathBuf>) -> Result<PathBuf> {
+    match file {
+        Some(file) => Ok(file),
+        None => latest_save_filename().map_err(|err| {
+            format!("no FILE given and no trace found in the output directory: {err}").into()
+        }),
+    }
+}
+
+/// Reads a whole trace, naming the file in any error.
+fn load_trace(path: PathBuf) -> Result<Trace> {
+    read_trace(path.clone()).map_err(|err| format!("{}: {err}", path.display()).into())
+}
+
+/// Reads only the spans of a trace, naming the file in any error.
+fn load_events(path: PathBuf) -> Result<Vec<Event>> {
+    read_events(path.clone()).map_err(|err| format!("{}: {err}", path.display()).into())
+}
+
+/// Writes a trace in the racy format, naming the file in any error.
+fn save_trace(path: PathBuf, trace: &Trace) -> Result<()> {
+    write_trace(path.clone(), trace).map_err(|err| format!("{}: {err}", path.display()).into())
+}
+
+/// Writes a command's report to stdout, stopping quietly once whatever
+/// reads it has had enough, e.g. `racy-cli dump | head`.
+fn print_report(report: &str) -> Result<()> {
+    match io::stdout().lock().write_all(report.as_bytes()) {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Nanoseconds in the largest unit that keeps them above one.
+fn format_duration(nanos: u64) -> String {
+    match nanos {
+        0..1_000 => format!("{nanos}ns"),
+        1_000..1_000_000 => format!("{:.2}µs", nanos as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.2}ms", nanos as f64 / 1e6),
+        _ => format!("{:.2}s", nanos as f64 / 1e9),
+    }
 }
@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+use common::merge_traces;
+
+use crate::{Result, load_trace, save_trace};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Traces to combine
+    #[arg(required = true, num_args = 2..)]
+    inputs: Vec<PathBuf>,
+    /// Where to write the merged trace
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let traces = args
+        .inputs
+        .into_iter()
+        .map(load_trace)
+        .collect::<Result<Vec<_>>>()?;
+    save_trace(args.output, &merge_traces(traces))
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use common::{Event, child_time, json_string, span_parents};
+
+use crate::{Result, format_duration, input_path, load_events, print_report};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Trace to summarize
+    file: Option<PathBuf>,
+    /// Column to sort by, largest first except for names
+    #[arg(short, long, value_enum, default_value_t = Column::Total)]
+    sort: Column,
+    /// Only count spans of this thread id
+    #[arg(long)]
+    thread: Option<u64>,
+    /// Only count spans of this process
+    #[arg(long)]
+    pid: Option<u32>,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Column {
+    Name,
+    Count,
+    Total,
+    #[value(name = "self")]
+    SelfTime,
+    Mean,
+    Min,
+    Max,
+    P50,
+    P95,
+    P99,
+    Stddev,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// An aligned table with readable durations
+    Text,
+    /// Durations in nanoseconds
+    Csv,
+    /// An array of objects, durations in nanoseconds
+    Json,
+}
+
+/// Aggregate of every span sharing a name, durations in nanoseconds.
+#[derive(Debug, Clone, PartialEq)]
+struct NameStats {
+    name: String,
+    count: u64,
+    total: u64,
+    /// Total minus the time spent in child spans.
+    self_time: u64,
+    mean: f64,
+    min: u64,
+    max: u64,
+    p50: u64,
+    p95: u64,
+    p99: u64,
+    stddev: f64,
+}
+
+impl NameStats {
+    /// Summarizes `durations`, which must be sorted and not empty.
+    fn new(name: String, durations: &[u64], self_time: u64) -> Self {
+        let count = durations.len() as u64;
+        let total: u64 = durations.iter().sum();
+        let mean = total as f64 / count as f64;
+        let variance = durations
+            .iter()
+            .map(|&duration| (duration as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+        // Nearest-rank percentile.
+        let percentile = |p: f64| {
+            let rank = (p / 100.0 * count as f64).ceil() as usize;
+            durations[rank.clamp(1, durations.len()) - 1]
+        };
+        Self {
+            name,
+            count,
+            total,
+            self_time,
+            mean,
+            min: durations[0],
+            max: durations[durations.len() - 1],
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            stddev: variance.sqrt(),
+        }
+    }
+
+    fn cmp_by(&self, other: &Self, column: Column) -> std::cmp::Ordering {
+        let descending = match column {
+            Column::Name => return self.name.cmp(&other.name),
+            Column::Count => other.count.cmp(&self.count),
+            Column::Total => other.total.cmp(&self.total),
+            Column::SelfTime => other.self_time.cmp(&self.self_time),
+            Column::Mean => other.mean.total_cmp(&self.mean),
+            Column::Min => other.min.cmp(&self.min),
+            Column::Max => other.max.cmp(&self.max),
+            Column::P50 => other.p50.cmp(&self.p50),
+            Column::P95 => other.p95.cmp(&self.p95),
+            Column::P99 => other.p99.cmp(&self.p99),
+            Column::Stddev => other.stddev.total_cmp(&self.stddev),
+        };
+        descending.then_with(|| self.name.cmp(&other.name))
+    }
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let events = load_events(input_path(args.file)?)?;
+    let keep = |event: &Event| {
+        args.thread.is_none_or(|thread| thread == event.id)
+            && args.pid.is_none_or(|pid| pid == event.pid)
+    };
+
+    let mut stats = aggregate(&events, keep);
+    stats.sort_by(|a, b| a.cmp_by(b, args.sort));
+
+    let report = match args.format {
+        Format::Text => {
+            let kept: Vec<&Event> = events.iter().filter(|event| keep(event)).collect();
+            text_report(&kept, &stats)?
+        }
+        Format::Csv => csv_report(&stats)?,
+        Format::Json => json_report(&stats)?,
+    };
+    print_report(&report)
+}
+
+/// Per-name statistics of the spans `keep` accepts.
+///
+/// Children are found among all spans, so filtering doesn't inflate self time.
+fn aggregate(events: &[Event], keep: impl Fn(&Event) -> bool) -> Vec<NameStats> {
+    let child_time = child_time(events, &span_parents(events));
+
+    let mut by_name: HashMap<&str, (Vec<u64>, u64)> = HashMap::new();
+    for (index, event) in events.iter().enumerate().filter(|(_, event)| keep(event)) {
+        let (durations, self_time) = by_name.entry(&event.name).or_default();
+        durations.push(event.duration);
+        *self_time += event.duration.saturating_sub(child_time[index]);
+    }
+
+    by_name
+        .into_iter()
+        .map(|(name, (mut durations, self_time))| {
+            durations.sort_unstable();
+            NameStats::new(name.to_string(), &durations, self_time)
+        })
+        .collect()
+}
+
+fn text_report(events: &[&Event], stats: &[NameStats]) -> Result<String> {
+    let start = events
+        .iter()
+        .map(|event| event.timestamp)
+        .min()
+        .unwrap_or(0);
+    let end = events
+        .iter()
+        .map(|event| event.timestamp + event.duration as u128)
+        .max()
+        .unwrap_or(0);
+    let threads: HashSet<(u32, u64)> = events.iter().map(|event| (event.pid, event.id)).collect();
+    let processes: HashSet<u32> = events.iter().map(|event| event.pid).collect();
+
+    let mut report = String::new();
+    writeln!(report, "spans      {}", events.len())?;
+    writeln!(report, "names      {}", stats.len())?;
+    writeln!(report, "threads    {}", threads.len())?;
+    writeln!(report, "processes  {}", processes.len())?;
+    writeln!(
+        report,
+        "duration   {}",
+        format_duration((end - start) as u64)
+    )?;
+    writeln!(report)?;
+
+    let width = stats
+        .iter()
+        .map(|stats| stats.name.chars().count())
+        .chain(["name".len()])
+        .max()
+        .unwrap_or(0);
+    writeln!(
+        report,
+        "{:<width$} {:>9} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "name", "count", "total", "self", "mean", "min", "max", "p50", "p95", "p99", "stddev"
+    )?;
+    for stats in stats {
+        writeln!(
+            report,
+            "{:<width$} {:>9} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            stats.name,
+            stats.count,
+            format_duration(stats.total),
+            format_duration(stats.self_time),
+            format_duration(stats.mean as u64),
+            format_duration(stats.min),
+            format_duration(stats.max),
+            format_duration(stats.p50),
+            format_duration(stats.p95),
+            format_duration(stats.p99),
+            format_duration(stats.stddev as u64),
+        )?;
+    }
+    Ok(report)
+}
+
+fn csv_report(stats: &[NameStats]) -> Result<String> {
+    let mut report = "name,count,total,self,mean,min,max,p50,p95,p99,stddev\n".to_string();
+    for stats in stats {
+        // Quote names that would otherwise break the row.
+        let name = if stats.name.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", stats.name.replace('"', "\"\""))
+        } else {
+            stats.name.clone()
+        };
+        writeln!(
+            report,
+            "{},{},{},{},{:.1},{},{},{},{},{},{:.1}",
+            name,
+            stats.count,
+            stats.total,
+            stats.self_time,
+            stats.mean,
+            stats.min,
+            stats.max,
+            stats.p50,
+            stats.p95,
+            stats.p99,
+            stats.stddev
+        )?;
+    }
+    Ok(report)
+}
+
+fn json_report(stats: &[NameStats]) -> Result<String> {
+    let mut report = "[".to_string();
+    for (index, stats) in stats.iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        write!(
+            report,
+            "{}\n  {{\"name\": {}, \"count\": {}, \"total\": {}, \"self\": {}, \
+             \"mean\": {:.1}, \"min\": {}, \"max\": {}, \"p50\": {}, \"p95\": {}, \
+             \"p99\": {}, \"stddev\": {:.1}}}",
+            separator,
+            json_string(&stats.name),
+            stats.count,
+            stats.total,
+            stats.self_time,
+            stats.mean,
+            stats.min,
+            stats.max,
+            stats.p50,
+            stats.p95,
+            stats.p99,
+            stats.stddev
+        )?;
+    }
+    report += "\n]\n";
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(span_id: u64, parent_id: u64, timestamp: u128, duration: u64, name: &str) -> Event {
+        Event {
+            id: 1,
+            span_id,
+            parent_id,
+            timestamp,
+            duration,
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn self_time_excludes_children() {
+        let linked = vec![
+            span(1, 0, 0, 100, "outer"),
+            span(2, 1, 10, 30, "inner"),
+            span(3, 1, 50, 20, "inner"),
+        ];
+        // The same call tree without ids, nested by time instead.
+        let unlinked = vec![
+            span(0, 0, 0, 100, "outer"),
+            span(0, 0, 10, 30, "inner"),
+            span(0, 0, 50, 20, "inner"),
+        ];
+
+        for events in [linked, unlinked] {
+            let mut stats = aggregate(&events, |_| true);
+            stats.sort_by(|a, b| a.cmp_by(b, Column::Name));
+            let [inner, outer] = stats.as_slice() else {
+                panic!("expected two names, got {stats:?}");
+            };
+            assert_eq!(outer.self_time, 50);
+            assert_eq!(inner.self_time, 50);
+            assert_eq!(
+                (inner.count, inner.min, inner.max, inner.p50),
+                (2, 20, 30, 20)
+            );
+            assert_eq!(inner.stddev, 5.0);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use common::{Event, FlowPhase, Trace, read_trace};
+
+use crate::{Result, input_path, print_report};
+
+/// Problems listed per file before the rest are only counted.
+const MAX_REPORTED: usize = 20;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Traces to check
+    files: Vec<PathBuf>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let files = if args.files.is_empty() {
+        vec![input_path(None)?]
+    } else {
+        args.files
+    };
+
+    let mut invalid = 0;
+    let mut report = String::new();
+    for file in files.iter() {
+        let path = file.display();
+        let problems = match read_trace(file.clone()) {
+            Ok(trace) => problems(&trace),
+            Err(err) => vec![format!("unreadable: {err}")],
+        };
+        if problems.is_empty() {
+            report += &format!("{path}: ok\n");
+            continue;
+        }
+
+        invalid += 1;
+        for problem in problems.iter().take(MAX_REPORTED) {
+            report += &format!("{path}: {problem}\n");
+        }
+        if problems.len() > MAX_REPORTED {
+            report += &format!("{path}: and {} more\n", problems.len() - MAX_REPORTED);
+        }
+    }
+    print_report(&report)?;
+
+    if invalid > 0 {
+        return Err(format!("{} of {} traces are invalid", invalid, files.len()).into());
+    }
+    Ok(())
+}
+
+/// Everything about `trace` a writer shouldn't have produced.
+fn problems(trace: &Trace) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let mut spans: HashMap<(u32, u64), &Event> = HashMap::new();
+    for event in trace.events.iter().filter(|event| event.span_id != 0) {
+        if spans.insert((event.pid, event.span_id), event).is_some() {
+            problems.push(format!(
+                "span id {} is used twice in process {}",
+                event.span_id, event.pid
+            ));
+        }
+    }
+
+    for event in spans.values() {
+        // A task outlives the span it was created in.
+        if event.parent_id == 0 || event.task_id == event.span_id {
+            continue;
+        }
+        let Some(parent) = spans.get(&(event.pid, event.parent_id)) else {
+            problems.push(format!(
+                "span {} ({}) has parent {}, which is not in the trace",
+                event.span_id, event.name, event.parent_id
+            ));
+            continue;
+        };
+        if event.depth != parent.depth + 1 {
+            problems.push(format!(
+                "span {} ({}) has depth {}, but its parent has depth {}",
+                event.span_id, event.name, event.depth, parent.depth
+            ));
+        }
+        let end = |event: &Event| event.timestamp + event.duration as u128;
+        if event.timestamp < parent.timestamp || end(event) > end(parent) {
+            problems.push(format!(
+                "span {} ({}) is not within its parent {} ({})",
+                event.span_id, event.name, parent.span_id, parent.name
+            ));
+        }
+    }
+
+    let begins: HashSet<u64> = trace
+        .flows
+        .iter()
+        .filter(|flow| flow.phase == FlowPhase::Begin)
+        .map(|flow| flow.id)
+        .collect();
+    for flow in trace.flows.iter() {
+        if flow.span_id != 0 && !spans.contains_key(&(flow.pid, flow.span_id)) {
+            problems.push(format!(
+                "flow {} refers to span {}, which is not in the trace",
+                flow.id, flow.span_id
+            ));
+        }
+        if flow.phase == FlowPhase::End && !begins.contains(&flow.id) {
+            problems.push(format!("flow {} ends without beginning", flow.id));
+        }
+    }
+
+    problems.sort();
+    problems
+}
@@ -0,0 +1,125 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// Fixed capacity single-producer single-consumer queue.
+///
+/// The recording thread pushes and the flusher pops; neither side ever waits
+/// for the other. When the buffer is full new values are dropped and counted.
+pub struct RingBuffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    mask: usize,
+    /// Next slot to pop, only advanced by the consumer.
+    head: AtomicUsize,
+    /// Next slot to push, only advanced by the producer.
+    tail: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T> RingBuffer<T> {
+    /// Creates a buffer holding at least `capacity` values.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect();
+        Self {
+            slots,
+            mask: capacity - 1,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    /// Number of values rejected because the buffer was full.
+    pub fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    /// Appends `value`, returning `false` if the buffer was full.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may push at a time.
+    pub unsafe fn push(&self, value: T) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.capacity() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        // The slot at `tail` is not visible to the consumer until `tail` moves.
+        unsafe { (*self.slots[tail & self.mask].get()).write(value) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Removes the oldest value.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may pop at a time.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        // The producer published this slot with the release store of `tail`.
+        let value = unsafe { (*self.slots[head & self.mask].get()).assume_init_read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        // `&mut self` rules out any concurrent producer or consumer.
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_values_when_full_and_wraps_around() {
+        let buffer = RingBuffer::new(4);
+        unsafe {
+            for i in 0..4 {
+                assert!(buffer.push(i));
+            }
+            assert!(!buffer.push(4));
+            assert_eq!(buffer.take_dropped(), 1);
+
+            assert_eq!(buffer.pop(), Some(0));
+            assert_eq!(buffer.pop(), Some(1));
+            assert!(buffer.push(5));
+            assert_eq!(buffer.len(), 3);
+
+            assert_eq!(buffer.pop(), Some(2));
+            assert_eq!(buffer.pop(), Some(3));
+            assert_eq!(buffer.pop(), Some(5));
+            assert_eq!(buffer.pop(), None);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use common::OutputPath;
+
+use crate::profiler::{self, ProfilerGuard};
+
+/// Where the profiler writes its file.
+///
+/// Starts out from the `RACY_OUTPUT` environment variable, or a file named
+/// after the executable, pid and start time in the temp directory. Settings
+/// made here take precedence over the environment.
+///
+/// ```ignore
+/// let _profiler = ProfilerConfig::new()
+///     .output_dir("traces")
+///     .file_name("{exe}-{pid}.bin")
+///     .flush_on_signals(true)
+///     .init();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProfilerConfig {
+    pub(crate) output: OutputPath,
+    pub(crate) flush_on_signals: bool,
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self {
+            output: OutputPath::from_env(),
+            flush_on_signals: false,
+        }
+    }
+}
+
+impl ProfilerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output.dir = dir.into();
+        self
+    }
+
+    /// Sets the file name, in which `{pid}`, `{exe}` and `{timestamp}` are
+    /// replaced by the process id, executable name and UNIX start time.
+    ///
+    /// An existing file is never overwritten, a numbered name is used instead.
+    pub fn file_name(mut self, template: impl Into<String>) -> Self {
+        self.output.template = template.into();
+        self
+    }
+
+    /// Flushes the recording on SIGINT, SIGTERM and SIGHUP before they are
+    /// handled as usual, so an interrupted run still leaves a complete file.
+    pub fn flush_on_signals(mut self, enabled: bool) -> Self {
+        self.flush_on_signals = enabled;
+        self
+    }
+
+    /// Starts recording, unless the profiler is already running.
+    pub fn init(self) -> ProfilerGuard {
+        profiler::start(self)
+    }
+}
@@ -0,0 +1,22 @@
+use std::time::Instant;
+
+use common::{CounterRecord, CounterValue};
+
+use crate::{
+    names,
+    profiler::{Record, current_thread_id, record, timestamp},
+};
+
+/// Records the current value of a numeric time series, e.g.
+/// `counter("queue_len", queue.len())`.
+///
+/// Samples with the same name form one track in the viewer, whichever thread
+/// recorded them.
+pub fn counter(name: &'static str, value: impl Into<CounterValue>) {
+    record(Record::Counter(CounterRecord {
+        id: current_thread_id(),
+        timestamp: timestamp(Instant::now()),
+        name: names::intern_static(name),
+        value: value.into().0,
+    }));
+}
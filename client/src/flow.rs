@@ -0,0 +1,30 @@
+use std::time::Instant;
+
+use common::{Flow, FlowPhase};
+
+use crate::profiler::{Record, current_span_id, current_thread_id, record, timestamp};
+
+/// Starts a flow from the innermost open span on this thread.
+///
+/// The viewer draws an arrow from here to every [`flow_end`] with the same
+/// `id`, on whichever thread it happens, e.g. to follow a request from the
+/// span that queued it to the one that handled it.
+pub fn flow_begin(id: u64) {
+    record_flow(id, FlowPhase::Begin);
+}
+
+/// Ends the flow `id` in the innermost open span on this thread.
+pub fn flow_end(id: u64) {
+    record_flow(id, FlowPhase::End);
+}
+
+fn record_flow(id: u64, phase: FlowPhase) {
+    record(Record::Flow(Flow {
+        id,
+        phase,
+        pid: 0,
+        thread: current_thread_id(),
+        span_id: current_span_id(),
+        timestamp: timestamp(Instant::now()),
+    }));
+}
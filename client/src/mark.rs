@@ -0,0 +1,31 @@
+use std::time::Instant;
+
+use common::{Arg, InstantScope, MarkRecord};
+
+use crate::{
+    names,
+    profiler::{Record, current_thread_id, record, timestamp},
+};
+
+/// Records an instant event on the current thread, e.g. `mark("frame_begin")`.
+pub fn mark(name: &'static str) {
+    mark_with(name, InstantScope::Thread, [] as [Arg; 0]);
+}
+
+/// Records an instant event concerning `scope`, carrying key/value arguments.
+///
+/// The viewer draws thread marks on their thread only, and process and global
+/// marks across every thread.
+pub fn mark_with<A: Into<Arg>>(
+    name: &'static str,
+    scope: InstantScope,
+    args: impl IntoIterator<Item = A>,
+) {
+    record(Record::Mark(MarkRecord {
+        id: current_thread_id(),
+        timestamp: timestamp(Instant::now()),
+        name: names::intern_static(name),
+        scope,
+        args: args.into_iter().map(Into::into).collect(),
+    }));
+}
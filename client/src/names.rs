@@ -0,0 +1,233 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        LazyLock, Mutex, OnceLock, PoisonError,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use common::{Location, RecordWriter};
+
+static STRINGS: LazyLock<Mutex<StringTable>> = LazyLock::new(Default::default);
+
+thread_local! {
+    /// Ids of the plain `&'static str` names this thread has interned, by
+    /// address, see [`intern_static`].
+    static STATIC_NAMES: RefCell<HashMap<(usize, usize), u32>> = RefCell::new(HashMap::new());
+    /// Ids of method spans recorded under a Self type other than the first
+    /// one their call site saw, by call site and type, see
+    /// [`SpanName::method_id`].
+    static METHOD_NAMES: RefCell<HashMap<(usize, &'static str), u32>> = RefCell::new(HashMap::new());
+}
+
+/// Span name interned into the file's string table on first use.
+///
+/// Declared as a `static` by `#[profile]` and `profile_scope!`, so each call
+/// site looks its name up once and afterwards only records the id.
+pub struct SpanName {
+    name: &'static str,
+    location: Option<SourceLocation>,
+    category: Option<&'static str>,
+    /// Record one call out of this many.
+    sample_every: u32,
+    calls: AtomicU32,
+    id: AtomicU32,
+    /// Self type `id` was interned under, for spans named by
+    /// [`SpanName::method_id`].
+    self_type: OnceLock<&'static str>,
+}
+
+#[derive(Clone, Copy)]
+struct SourceLocation {
+    module_path: &'static str,
+    file: &'static str,
+    line: u32,
+}
+
+impl SpanName {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            location: None,
+            category: None,
+            sample_every: 1,
+            calls: AtomicU32::new(0),
+            id: AtomicU32::new(0),
+            self_type: OnceLock::new(),
+        }
+    }
+
+    /// Name of a span declared at the given place, usually filled in from
+    /// `module_path!()`, `file!()` and `line!()`.
+    pub const fn with_location(
+        name: &'static str,
+        module_path: &'static str,
+        file: &'static str,
+        line: u32,
+    ) -> Self {
+        Self {
+            name,
+            location: Some(SourceLocation {
+                module_path,
+                file,
+                line,
+            }),
+            category: None,
+            sample_every: 1,
+            calls: AtomicU32::new(0),
+            id: AtomicU32::new(0),
+            self_type: OnceLock::new(),
+        }
+    }
+
+    /// Tags the span with a category, which the viewer colours spans by.
+    pub const fn with_category(mut self, category: &'static str) -> Self {
+        self.category = Some(category);
+        self
+    }
+
+    /// Only records every `every`th call, see [`SpanName::sample`].
+    pub const fn sampled(mut self, every: u32) -> Self {
+        self.sample_every = if every == 0 { 1 } else { every };
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Counts a call and tells whether it should be recorded, which is the
+    /// first of every `every` calls given to [`SpanName::sampled`].
+    pub fn sample(&self) -> bool {
+        self.sample_every == 1
+            || self
+                .calls
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.sample_every)
+    }
+
+    pub fn id(&self) -> u32 {
+        match self.id.load(Ordering::Relaxed) {
+            0 => {
+                let id = self.intern_as(Cow::Borrowed(self.name));
+                self.id.store(id, Ordering::Relaxed);
+                id
+            }
+            id => id,
+        }
+    }
+
+    /// Id of the span of a method of `T`, named `Type::name` after the last
+    /// path segment of `T`'s name without generic arguments.
+    ///
+    /// `#[profile]` on a single method uses this, since the attribute can't
+    /// see the impl block around it. The id is cached like [`SpanName::id`]
+    /// for the first `T` a call site sees. Others, such as the implementors
+    /// of a trait's default method, are cached per thread.
+    pub fn method_id<T: ?Sized>(&self) -> u32 {
+        let self_type = std::any::type_name::<T>();
+        if *self.self_type.get_or_init(|| self_type) == self_type {
+            return match self.id.load(Ordering::Relaxed) {
+                0 => {
+                    let id = self.intern_method(self_type);
+                    self.id.store(id, Ordering::Relaxed);
+                    id
+                }
+                id => id,
+            };
+        }
+
+        let site = self as *const Self as usize;
+        METHOD_NAMES
+            .try_with(|names| {
+                *names
+                    .borrow_mut()
+                    .entry((site, self_type))
+                    .or_insert_with(|| self.intern_method(self_type))
+            })
+            .unwrap_or_else(|_| self.intern_method(self_type))
+    }
+
+    fn intern_method(&self, self_type: &str) -> u32 {
+        // `app::parser::Parser<T>` is shown as `Parser`.
+        let path = self_type.split('<').next().unwrap_or(self_type);
+        let type_name = path.rsplit("::").next().unwrap_or(path);
+        self.intern_as(Cow::Owned(format!("{}::{}", type_name, self.name)))
+    }
+
+    fn intern_as(&self, name: Cow<'static, str>) -> u32 {
+        let location = self.location.map(|location| Location {
+            module_path: location.module_path.to_string(),
+            file: location.file.to_string(),
+            line: location.line,
+        });
+        intern(name, location, self.category.map(Cow::Borrowed))
+    }
+}
+
+type Entry = (
+    Cow<'static, str>,
+    Option<Location>,
+    Option<Cow<'static, str>>,
+);
+
+#[derive(Default)]
+struct StringTable {
+    ids: HashMap<Entry, u32>,
+    entries: Vec<Entry>,
+    /// How many of `entries` are already in the output file.
+    written: usize,
+}
+
+/// Returns the string table id of `name` declared at `location` under
+/// `category`, adding it if needed. Ids start at 1.
+pub(crate) fn intern(
+    name: Cow<'static, str>,
+    location: Option<Location>,
+    category: Option<Cow<'static, str>>,
+) -> u32 {
+    let entry = (name, location, category);
+    let mut table = STRINGS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&id) = table.ids.get(&entry) {
+        return id;
+    }
+
+    table.entries.push(entry.clone());
+    let id = table.entries.len() as u32;
+    table.ids.insert(entry, id);
+    id
+}
+
+/// Returns the string table id of `name`, with no location or category.
+///
+/// Each thread remembers the ids of the names it has seen, so calls from
+/// marks and counters on the recording path only take the table's lock the
+/// first time a thread uses a name.
+pub(crate) fn intern_static(name: &'static str) -> u32 {
+    let key = (name.as_ptr() as usize, name.len());
+    STATIC_NAMES
+        .try_with(|names| {
+            *names
+                .borrow_mut()
+                .entry(key)
+                .or_insert_with(|| intern(Cow::Borrowed(name), None, None))
+        })
+        .unwrap_or_else(|_| intern(Cow::Borrowed(name), None, None))
+}
+
+/// Writes the strings interned since the last call.
+pub(crate) fn write_new_strings(writer: &mut RecordWriter) {
+    let mut table = STRINGS.lock().unwrap_or_else(PoisonError::into_inner);
+    for (index, (name, location, category)) in table.entries.iter().enumerate().skip(table.written)
+    {
+        writer.name(
+            index as u32 + 1,
+            name,
+            location.as_ref(),
+            category.as_deref(),
+        );
+    }
+    table.written = table.entries.len();
+}
@@ -0,0 +1,178 @@
+use std::{
+    borrow::Cow,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use common::{Arg, ArgValue, CounterValue, InstantScope};
+
+/// Span name that is never interned.
+pub struct SpanName {
+    name: &'static str,
+}
+
+impl SpanName {
+    pub const fn new(name: &'static str) -> Self {
+        Self { name }
+    }
+
+    pub const fn with_location(
+        name: &'static str,
+        _module_path: &'static str,
+        _file: &'static str,
+        _line: u32,
+    ) -> Self {
+        Self { name }
+    }
+
+    pub const fn with_category(self, _category: &'static str) -> Self {
+        self
+    }
+
+    pub const fn sampled(self, _every: u32) -> Self {
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline(always)]
+    pub fn sample(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> u32 {
+        0
+    }
+
+    #[inline(always)]
+    pub fn method_id<T: ?Sized>(&self) -> u32 {
+        0
+    }
+}
+
+/// Span guard that records nothing.
+pub struct ScopedProfiler;
+
+impl ScopedProfiler {
+    #[inline(always)]
+    pub fn new(_name: &SpanName) -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub fn method<T: ?Sized>(_name: &SpanName) -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub fn with_metadata<A: Into<Arg>>(
+        _name: &SpanName,
+        _metadata: impl IntoIterator<Item = A>,
+    ) -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub fn arg(&mut self, _key: impl Into<Cow<'static, str>>, _value: impl Into<ArgValue>) {}
+
+    #[inline(always)]
+    pub fn named(_name: impl Into<Cow<'static, str>>) -> Self {
+        Self
+    }
+}
+
+/// Polls `F` without recording anything.
+pub struct ProfiledFuture<F> {
+    future: F,
+}
+
+impl<F> ProfiledFuture<F> {
+    #[inline(always)]
+    pub fn new(_name: &SpanName, future: F) -> Self {
+        Self { future }
+    }
+
+    #[inline(always)]
+    pub fn method<T: ?Sized>(_name: &SpanName, future: F) -> Self {
+        Self { future }
+    }
+}
+
+impl<F: Future> Future for ProfiledFuture<F> {
+    type Output = F::Output;
+
+    #[inline(always)]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: `future` is pinned along with `self` and never moved out.
+        unsafe { self.map_unchecked_mut(|this| &mut this.future) }.poll(cx)
+    }
+}
+
+#[inline(always)]
+pub fn init_profiler() -> ProfilerGuard {
+    ProfilerGuard
+}
+
+#[inline(always)]
+pub fn shutdown() {}
+
+#[inline(always)]
+pub fn mark(_name: &'static str) {}
+
+#[inline(always)]
+pub fn flow_begin(_id: u64) {}
+
+#[inline(always)]
+pub fn flow_end(_id: u64) {}
+
+#[inline(always)]
+pub fn counter(_name: &'static str, _value: impl Into<CounterValue>) {}
+
+#[inline(always)]
+pub fn mark_with<A: Into<Arg>>(
+    _name: &'static str,
+    _scope: InstantScope,
+    _args: impl IntoIterator<Item = A>,
+) {
+}
+
+/// Profiler settings that are accepted and ignored.
+#[derive(Debug, Clone, Default)]
+pub struct ProfilerConfig;
+
+impl ProfilerConfig {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn output_dir(self, _dir: impl Into<PathBuf>) -> Self {
+        self
+    }
+
+    pub fn file_name(self, _template: impl Into<String>) -> Self {
+        self
+    }
+
+    pub fn flush_on_signals(self, _enabled: bool) -> Self {
+        self
+    }
+
+    #[inline(always)]
+    pub fn init(self) -> ProfilerGuard {
+        ProfilerGuard
+    }
+}
+
+/// Guard with nothing to flush.
+#[must_use = "the guard flushes the recording when dropped, bind it to a variable"]
+pub struct ProfilerGuard;
+
+#[inline(always)]
+pub fn output_path() -> Option<&'static Path> {
+    None
+}
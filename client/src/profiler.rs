@@ -0,0 +1,606 @@
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    panic,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, OnceLock, PoisonError,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+use common::{
+    Arg, ArgValue, ClockSource, CounterRecord, Flow, Header, MarkRecord, OutputPath, ProcessInfo,
+    RecordWriter, SpanRecord, ThreadInfo, serialize_header,
+};
+use thread_local::ThreadLocal;
+
+use crate::{ProfilerConfig, buffer::RingBuffer, names, names::SpanName, signals};
+
+static BUFFERS: ThreadLocal<RingBuffer<Record>> = ThreadLocal::new();
+/// Output file, held by whoever is draining `BUFFERS`.
+static WRITER: Mutex<Option<File>> = Mutex::new(None);
+static FLUSHER: OnceLock<Thread> = OnceLock::new();
+static ATEXIT_REGISTERED: AtomicBool = AtomicBool::new(false);
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+static BUFFER_CAPACITY: usize = 1 << 12;
+static FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+static NEXT_SPAN_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+static CLOCK_ANCHOR: OnceLock<ClockAnchor> = OnceLock::new();
+static OUTPUT_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Anything a thread records, waiting in its buffer to be written out.
+#[derive(Debug)]
+pub(crate) enum Record {
+    Span(SpanRecord),
+    Mark(MarkRecord),
+    Counter(CounterRecord),
+    Flow(Flow),
+    Thread(ThreadInfo),
+}
+
+/// Pairs the monotonic clock with the wall-clock time recorded in the header.
+struct ClockAnchor {
+    instant: Instant,
+    header: Header,
+}
+
+fn clock_anchor() -> &'static ClockAnchor {
+    CLOCK_ANCHOR.get_or_init(|| ClockAnchor {
+        instant: Instant::now(),
+        header: Header::current(ClockSource::Monotonic),
+    })
+}
+
+/// Nanoseconds between the clock anchor and `instant`.
+pub(crate) fn timestamp(instant: Instant) -> u128 {
+    instant
+        .saturating_duration_since(clock_anchor().instant)
+        .as_nanos()
+}
+
+thread_local! {
+    /// Ids of the spans currently open on this thread, innermost last.
+    static SPAN_STACK: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    /// Id this thread records under, 0 until it records something.
+    static THREAD_ID: Cell<u64> = const { Cell::new(0) };
+    /// Flushes when the thread exits, see [`ThreadExitFlush`].
+    static EXIT_FLUSH: ThreadExitFlush = const { ThreadExitFlush };
+}
+
+/// Writes out what a thread recorded when it exits, rather than leaving it
+/// for a flusher that may never run again.
+struct ThreadExitFlush;
+
+impl Drop for ThreadExitFlush {
+    fn drop(&mut self) {
+        flush_or_report();
+    }
+}
+
+pub struct ScopedProfiler {
+    id: u64,
+    span_id: u64,
+    parent_id: u64,
+    depth: u32,
+    start: Instant,
+    name: u32,
+    args: Vec<Arg>,
+    pub(crate) task_id: u64,
+}
+
+impl ScopedProfiler {
+    pub fn new(name: &SpanName) -> Self {
+        Self::start(name.id())
+    }
+
+    /// Starts the span of a method of `T`, see [`SpanName::method_id`].
+    pub fn method<T: ?Sized>(name: &SpanName) -> Self {
+        Self::start(name.method_id::<T>())
+    }
+
+    /// Starts a span carrying key/value arguments, e.g.
+    /// `[("path", ArgValue::from(path)), ("retry", true.into())]`.
+    pub fn with_metadata<A: Into<Arg>>(
+        name: &SpanName,
+        metadata: impl IntoIterator<Item = A>,
+    ) -> Self {
+        let mut profiler = Self::start(name.id());
+        profiler.args.extend(metadata.into_iter().map(Into::into));
+        profiler
+    }
+
+    /// Attaches an argument to the span, for values only known once it has started.
+    pub fn arg(&mut self, key: impl Into<Cow<'static, str>>, value: impl Into<ArgValue>) {
+        self.args.push(Arg::new(key, value));
+    }
+
+    /// Starts a span whose name is only known at runtime.
+    ///
+    /// The name is looked up in the string table on every call, so prefer
+    /// [`ScopedProfiler::new`] with a static [`SpanName`] in hot code.
+    pub fn named(name: impl Into<Cow<'static, str>>) -> Self {
+        Self::start(names::intern(name.into(), None, None))
+    }
+
+    pub(crate) fn start(name: u32) -> Self {
+        let id = current_thread_id();
+        let span_id = next_span_id();
+        let (parent_id, depth) = SPAN_STACK
+            .try_with(|stack| {
+                let mut stack = stack.borrow_mut();
+                let parent = stack.last().copied().unwrap_or(0);
+                let depth = stack.len() as u32;
+                stack.push(span_id);
+                (parent, depth)
+            })
+            .unwrap_or((0, 0));
+        let start = Instant::now();
+        Self {
+            id,
+            span_id,
+            parent_id,
+            depth,
+            start,
+            name,
+            args: Vec::new(),
+            task_id: 0,
+        }
+    }
+}
+
+impl Drop for ScopedProfiler {
+    fn drop(&mut self) {
+        let end = Instant::now();
+
+        // Guards normally drop innermost first, but one that was moved or leaked
+        // may not be on top, so search for it instead of blindly popping.
+        let _ = SPAN_STACK.try_with(|stack| {
+            let mut stack = stack.borrow_mut();
+            if let Some(position) = stack.iter().rposition(|&id| id == self.span_id) {
+                stack.truncate(position);
+            }
+        });
+
+        record_span(SpanRecord {
+            id: self.id,
+            span_id: self.span_id,
+            parent_id: self.parent_id,
+            depth: self.depth,
+            duration: end.saturating_duration_since(self.start).as_nanos() as u64,
+            timestamp: timestamp(self.start),
+            name: self.name,
+            args: std::mem::take(&mut self.args),
+            task_id: self.task_id,
+        })
+    }
+}
+
+/// Id of this thread, announcing its name with a metadata record the first
+/// time it is asked for.
+pub(crate) fn current_thread_id() -> u64 {
+    THREAD_ID
+        .try_with(|id| {
+            if id.get() == 0 {
+                id.set(NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed));
+                // First record of this thread, make sure it gets flushed on
+                // exit. Not when its buffer is created: a new thread may be
+                // handed the buffer of one that exited.
+                let _ = EXIT_FLUSH.try_with(|_| {});
+                record(Record::Thread(ThreadInfo {
+                    pid: 0,
+                    id: id.get(),
+                    name: thread_name(),
+                }));
+            }
+            id.get()
+        })
+        .unwrap_or(0)
+}
+
+fn thread_name() -> String {
+    match thread::current().name() {
+        Some(name) => name.to_string(),
+        None => format!("thread {}", os_thread_id()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn os_thread_id() -> u64 {
+    // Safety: gettid has no preconditions.
+    unsafe { libc::gettid() as u64 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn os_thread_id() -> u64 {
+    // Safety: pthread_self has no preconditions.
+    unsafe { libc::pthread_self() as u64 }
+}
+
+/// Id of the innermost span open on this thread, 0 if there is none.
+pub(crate) fn current_span_id() -> u64 {
+    SPAN_STACK
+        .try_with(|stack| stack.borrow().last().copied().unwrap_or(0))
+        .unwrap_or(0)
+}
+
+pub(crate) fn next_span_id() -> u64 {
+    NEXT_SPAN_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub(crate) fn record_span(span: SpanRecord) {
+    record(Record::Span(span));
+}
+
+pub(crate) fn record(record: Record) {
+    if SHUT_DOWN.load(Ordering::Relaxed) {
+        return;
+    }
+    let buffer = BUFFERS.get_or(|| RingBuffer::new(BUFFER_CAPACITY));
+    // Safety: a thread only ever pushes into its own buffer.
+    let pushed = unsafe { buffer.push(record) };
+    if pushed
+        && buffer.len() == buffer.capacity() / 2
+        && let Some(flusher) = FLUSHER.get()
+    {
+        flusher.unpark();
+    }
+}
+
+/// Drains every thread's buffer into the output file.
+fn flush() -> Result<(), Box<dyn Error + 'static>> {
+    let mut writer = WRITER.lock().unwrap_or_else(PoisonError::into_inner);
+    drain_into(&mut writer)
+}
+
+pub(crate) fn flush_or_report() {
+    if let Err(err) = flush() {
+        eprintln!("Racy error: {err}")
+    }
+}
+
+fn drain_into(writer: &mut Option<File>) -> Result<(), Box<dyn Error + 'static>> {
+    let Some(file) = writer.as_mut() else {
+        return Ok(());
+    };
+
+    let mut popped = Vec::new();
+    let mut dropped = 0;
+    for buffer in BUFFERS.iter() {
+        // Only take what is there now, so a busy thread can't keep us here.
+        for _ in 0..buffer.len() {
+            // Safety: holding `WRITER` makes this the only consumer.
+            match unsafe { buffer.pop() } {
+                Some(record) => popped.push(record),
+                None => break,
+            }
+        }
+        dropped += buffer.take_dropped();
+    }
+
+    if dropped > 0 {
+        eprintln!("Racy: dropped {dropped} events, recording buffers were full");
+    }
+
+    // Names are interned before their records are pushed, so every name the
+    // popped records refer to is in the table by now.
+    let mut records = RecordWriter::new();
+    names::write_new_strings(&mut records);
+    for record in popped.iter() {
+        match record {
+            Record::Span(span) => records.span(span),
+            Record::Mark(mark) => records.mark(mark),
+            Record::Counter(counter) => records.counter(counter),
+            Record::Flow(flow) => records.flow(flow),
+            Record::Thread(thread) => records.thread(thread),
+        }
+    }
+    if !records.is_empty() {
+        file.write_all(&records.into_bytes())?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+fn run_flusher() {
+    while !SHUT_DOWN.load(Ordering::Relaxed) {
+        thread::park_timeout(FLUSH_INTERVAL);
+        flush_or_report();
+    }
+}
+
+extern "C" fn dump_completion_marker() {
+    flush_or_report();
+}
+
+/// Writes out whatever was recorded so far when a thread panics, before
+/// unwinding starts or the process aborts.
+fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // The panicking thread may be the one holding the writer.
+        if let Ok(mut writer) = WRITER.try_lock()
+            && let Err(err) = drain_into(&mut writer)
+        {
+            eprintln!("Racy error: {err}")
+        }
+        previous(info);
+    }));
+}
+
+/// Starts recording with the default [`ProfilerConfig`].
+pub fn init_profiler() -> ProfilerGuard {
+    ProfilerConfig::default().init()
+}
+
+/// Writes out everything recorded so far and stops recording.
+///
+/// Spans that end afterwards are discarded. Safe to call more than once.
+pub fn shutdown() {
+    if SHUT_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if let Some(flusher) = FLUSHER.get() {
+        flusher.unpark();
+    }
+
+    let mut writer = WRITER.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Err(err) = drain_into(&mut writer) {
+        eprintln!("Racy error: {err}")
+    }
+    // Closes the file.
+    *writer = None;
+}
+
+/// Returned by [`init_profiler`], flushes the recording when dropped.
+///
+/// Keep it alive in `main` so what was recorded is written out even when
+/// `main` unwinds from a panic. Recording carries on after the guard is gone.
+#[must_use = "the guard flushes the recording when dropped, bind it to a variable"]
+pub struct ProfilerGuard {
+    _private: (),
+}
+
+impl Drop for ProfilerGuard {
+    fn drop(&mut self) {
+        flush_or_report();
+    }
+}
+
+/// Path of the file being recorded to, once the profiler is started.
+pub fn output_path() -> Option<&'static Path> {
+    OUTPUT_PATH.get().map(PathBuf::as_path)
+}
+
+pub(crate) fn start(config: ProfilerConfig) -> ProfilerGuard {
+    if !ATEXIT_REGISTERED.swap(true, Ordering::SeqCst) {
+        let header = &clock_anchor().header;
+        let (path, mut file) = match create_output(&config.output, header) {
+            Ok(output) => output,
+            Err(err) => return record_nothing(format!("could not create output file: {err}")),
+        };
+        let mut process = RecordWriter::new();
+        process.process(&ProcessInfo::current());
+        if let Err(err) = file
+            .write_all(&serialize_header(header))
+            .and_then(|()| file.write_all(&process.into_bytes()))
+        {
+            return record_nothing(format!("could not write {}: {err}", path.display()));
+        }
+
+        let flusher = match thread::Builder::new()
+            .name("racy-flusher".to_string())
+            .spawn(run_flusher)
+        {
+            Ok(flusher) => flusher,
+            Err(err) => return record_nothing(format!("could not start flusher thread: {err}")),
+        };
+        let _ = FLUSHER.set(flusher.thread().clone());
+        *WRITER.lock().unwrap_or_else(PoisonError::into_inner) = Some(file);
+        let _ = OUTPUT_PATH.set(path);
+
+        install_panic_hook();
+        if config.flush_on_signals {
+            signals::install();
+        }
+        unsafe {
+            libc::atexit(dump_completion_marker);
+        }
+    }
+    ProfilerGuard { _private: () }
+}
+
+/// Reports why recording could not start and lets the program run on without
+/// it.
+fn record_nothing(reason: String) -> ProfilerGuard {
+    eprintln!("Racy error: {reason}");
+    SHUT_DOWN.store(true, Ordering::SeqCst);
+    ProfilerGuard { _private: () }
+}
+
+/// Creates a new file for `header`, numbering the name rather than touching a
+/// file some other run already wrote.
+fn create_output(output: &OutputPath, header: &Header) -> io::Result<(PathBuf, File)> {
+    fs::create_dir_all(&output.dir)?;
+    let path = output.file_for(header);
+
+    let mut candidate = path.clone();
+    for attempt in 1.. {
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((candidate, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let name = match path.extension() {
+                    Some(extension) => {
+                        format!("{}-{}.{}", stem, attempt, extension.to_string_lossy())
+                    }
+                    None => format!("{}-{}", stem, attempt),
+                };
+                candidate = path.with_file_name(name);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::InstantScope;
+
+    use crate::{ProfiledFuture, profile_scope};
+
+    fn drain_spans(buffer: &RingBuffer<Record>) -> Vec<SpanRecord> {
+        std::iter::from_fn(|| unsafe { buffer.pop() })
+            .filter_map(|record| match record {
+                Record::Span(span) => Some(span),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_spans_record_parent_and_depth() {
+        static OUTER: SpanName = SpanName::new("outer");
+        {
+            let _outer = ScopedProfiler::new(&OUTER);
+            profile_scope!("inner", rows = 3u32, cached = false);
+        }
+
+        let buffer = BUFFERS.get().unwrap();
+        let spans = drain_spans(buffer);
+        let outer = spans.iter().find(|span| span.name == OUTER.id()).unwrap();
+        let inner = spans.iter().find(|span| span.name != OUTER.id()).unwrap();
+        assert_eq!(outer.parent_id, 0);
+        assert_eq!(outer.depth, 0);
+        assert_eq!(inner.parent_id, outer.span_id);
+        assert_eq!(inner.depth, 1);
+        assert_eq!(inner.args[0], Arg::new("rows", 3u32));
+        assert_eq!(inner.args[1], Arg::new("cached", false));
+    }
+
+    #[test]
+    fn sampled_names_record_every_nth_call() {
+        static SAMPLED: SpanName = SpanName::new("sampled").sampled(3);
+        let calls: Vec<bool> = (0..6).map(|_| SAMPLED.sample()).collect();
+        assert_eq!(calls, [true, false, false, true, false, false]);
+    }
+
+    #[test]
+    fn async_polls_link_to_their_task() {
+        static TASK: SpanName = SpanName::new("task");
+        let mut pending = true;
+        let future = std::future::poll_fn(|cx| {
+            if std::mem::take(&mut pending) {
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            } else {
+                std::task::Poll::Ready(7)
+            }
+        });
+
+        let mut future = std::pin::pin!(ProfiledFuture::new(&TASK, future));
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(future.as_mut().poll(&mut cx), std::task::Poll::Ready(7));
+
+        let buffer = BUFFERS.get().unwrap();
+        let spans = drain_spans(buffer);
+        assert_eq!(spans.len(), 3);
+        let task = spans.last().unwrap();
+        assert_eq!(task.task_id, task.span_id);
+        assert!(spans[..2].iter().all(|poll| poll.task_id == task.span_id));
+    }
+
+    #[test]
+    fn existing_output_files_are_kept() {
+        let output = OutputPath {
+            dir: std::env::temp_dir().join(format!("racy-test-{}", std::process::id())),
+            template: "run.bin".to_string(),
+        };
+        let header = Header::current(ClockSource::Monotonic);
+
+        let (first, _) = create_output(&output, &header).unwrap();
+        let (second, _) = create_output(&output, &header).unwrap();
+        assert_eq!(first.file_name().unwrap(), "run.bin");
+        assert_eq!(second.file_name().unwrap(), "run-1.bin");
+
+        fs::remove_dir_all(&output.dir).unwrap();
+    }
+
+    #[test]
+    fn marks_are_buffered_with_their_scope() {
+        crate::mark_with("frame_begin", InstantScope::Process, [("frame", 3u32)]);
+
+        let buffer = BUFFERS.get().unwrap();
+        let records: Vec<Record> = std::iter::from_fn(|| unsafe { buffer.pop() })
+            .filter(|record| !matches!(record, Record::Thread(_)))
+            .collect();
+        let [Record::Mark(mark)] = records.as_slice() else {
+            panic!("expected a single mark, got {records:?}");
+        };
+        assert_eq!(mark.scope, InstantScope::Process);
+        assert_eq!(mark.args, vec![Arg::new("frame", 3u32)]);
+    }
+
+    #[test]
+    fn flows_attach_to_the_enclosing_span() {
+        static PRODUCER: SpanName = SpanName::new("producer");
+        let span_id = {
+            let producer = ScopedProfiler::new(&PRODUCER);
+            crate::flow_begin(5);
+            producer.span_id
+        };
+
+        let buffer = BUFFERS.get().unwrap();
+        let records: Vec<Record> = std::iter::from_fn(|| unsafe { buffer.pop() }).collect();
+        let flow = records
+            .iter()
+            .find_map(|record| match record {
+                Record::Flow(flow) => Some(flow),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(flow.id, 5);
+        assert_eq!(flow.span_id, span_id);
+    }
+
+    #[test]
+    fn threads_announce_their_name_once() {
+        static WORK: SpanName = SpanName::new("work");
+        let records = thread::Builder::new()
+            .name("rayon-worker-3".to_string())
+            .spawn(|| {
+                drop(ScopedProfiler::new(&WORK));
+                drop(ScopedProfiler::new(&WORK));
+                let buffer = BUFFERS.get().unwrap();
+                std::iter::from_fn(|| unsafe { buffer.pop() }).collect::<Vec<_>>()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let [
+            Record::Thread(thread),
+            Record::Span(first),
+            Record::Span(second),
+        ] = records.as_slice()
+        else {
+            panic!("expected the thread's name before its spans, got {records:?}");
+        };
+        assert_eq!(thread.name, "rayon-worker-3");
+        assert_eq!(first.id, thread.id);
+        assert_eq!(second.id, thread.id);
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    ffi::c_void,
+    io, mem, ptr,
+    sync::atomic::{AtomicI32, AtomicUsize, Ordering},
+    thread,
+};
+
+use libc::c_int;
+
+use crate::profiler::flush_or_report;
+
+/// Signals that end a process unless it handles them.
+const SIGNALS: [c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+/// How long a handler waits for the flush before letting the signal through.
+const FLUSH_TIMEOUT_MS: c_int = 1000;
+
+/// Write end of the pipe handlers send signal numbers down.
+static REQUEST_FD: AtomicI32 = AtomicI32::new(-1);
+/// Read end of the pipe the flush is acknowledged on.
+static DONE_FD: AtomicI32 = AtomicI32::new(-1);
+/// Dispositions the signals had before ours, in `SIGNALS` order.
+static PREVIOUS: [AtomicUsize; 3] = [const { AtomicUsize::new(libc::SIG_DFL) }; 3];
+
+/// Flushes the recording when one of `SIGNALS` arrives, then hands the signal
+/// to whatever would have handled it before.
+///
+/// Signals the process ignores are left alone, since the handler is gone after
+/// the first one and later ones would go unflushed.
+///
+/// Flushing takes locks and allocates, neither of which a signal handler may
+/// do, so the handler only wakes a thread that flushes and waits for it.
+pub(crate) fn install() {
+    let mut requests = [0; 2];
+    let mut done = [0; 2];
+    unsafe {
+        if libc::pipe(requests.as_mut_ptr()) != 0 || libc::pipe(done.as_mut_ptr()) != 0 {
+            let err = io::Error::last_os_error();
+            eprintln!("Racy error: could not install signal handlers: {err}");
+            return;
+        }
+    }
+    REQUEST_FD.store(requests[1], Ordering::SeqCst);
+    DONE_FD.store(done[0], Ordering::SeqCst);
+
+    let (request_reader, done_writer) = (requests[0], done[1]);
+    let spawned = thread::Builder::new()
+        .name("racy-signals".to_string())
+        .spawn(move || {
+            let mut signal = 0u8;
+            while unsafe { libc::read(request_reader, &mut signal as *mut u8 as *mut c_void, 1) }
+                == 1
+            {
+                flush_or_report();
+                unsafe { libc::write(done_writer, &signal as *const u8 as *const c_void, 1) };
+            }
+        });
+    if let Err(err) = spawned {
+        eprintln!("Racy error: could not install signal handlers: {err}");
+        return;
+    }
+
+    for (signal, previous) in SIGNALS.iter().zip(PREVIOUS.iter()) {
+        if is_ignored(*signal) {
+            continue;
+        }
+        let handler = handle as extern "C" fn(c_int) as libc::sighandler_t;
+        previous.store(unsafe { libc::signal(*signal, handler) }, Ordering::SeqCst);
+    }
+}
+
+/// Whether `signal` is currently ignored, without changing its disposition.
+fn is_ignored(signal: c_int) -> bool {
+    unsafe {
+        let mut current: libc::sigaction = mem::zeroed();
+        libc::sigaction(signal, ptr::null(), &mut current) == 0
+            && current.sa_sigaction == libc::SIG_IGN
+    }
+}
+
+extern "C" fn handle(signal: c_int) {
+    // Only async-signal-safe calls from here on.
+    let request = REQUEST_FD.load(Ordering::Relaxed);
+    let done = DONE_FD.load(Ordering::Relaxed);
+    let byte = signal as u8;
+    unsafe {
+        if libc::write(request, &byte as *const u8 as *const c_void, 1) == 1 {
+            // Time out in case this thread holds the writer lock itself.
+            let mut poll_fd = libc::pollfd {
+                fd: done,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if libc::poll(&mut poll_fd, 1, FLUSH_TIMEOUT_MS) == 1 {
+                let mut ack = 0u8;
+                libc::read(done, &mut ack as *mut u8 as *mut c_void, 1);
+            }
+        }
+
+        let previous = SIGNALS
+            .iter()
+            .position(|&handled| handled == signal)
+            .map_or(libc::SIG_DFL, |index| {
+                PREVIOUS[index].load(Ordering::Relaxed)
+            });
+        libc::signal(signal, previous);
+        libc::raise(signal);
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use common::{Arg, SpanRecord};
+
+use crate::{
+    SpanName,
+    profiler::{ScopedProfiler, current_thread_id, next_span_id, record_span, timestamp},
+};
+
+/// Future that records every poll of `F` as a span on the polling thread.
+///
+/// A second span covers the task from its first poll until it completes or is
+/// dropped. Poll spans point at it through their `task_id`, which is how the
+/// viewer ties together polls that ran on different threads.
+///
+/// `#[profile]` wraps the body of an `async fn` in one of these.
+pub struct ProfiledFuture<F> {
+    future: F,
+    name: u32,
+    task: Option<TaskSpan>,
+}
+
+struct TaskSpan {
+    id: u64,
+    span_id: u64,
+    start: Instant,
+}
+
+impl<F> ProfiledFuture<F> {
+    pub fn new(name: &SpanName, future: F) -> Self {
+        Self {
+            future,
+            name: name.id(),
+            task: None,
+        }
+    }
+
+    /// Profiles the future of an async method of `T`, see
+    /// [`SpanName::method_id`].
+    pub fn method<T: ?Sized>(name: &SpanName, future: F) -> Self {
+        Self {
+            future,
+            name: name.method_id::<T>(),
+            task: None,
+        }
+    }
+
+    fn finish(&mut self, args: Vec<Arg>) {
+        let Some(task) = self.task.take() else {
+            return;
+        };
+        let end = Instant::now();
+        record_span(SpanRecord {
+            id: task.id,
+            span_id: task.span_id,
+            parent_id: 0,
+            depth: 0,
+            duration: end.saturating_duration_since(task.start).as_nanos() as u64,
+            timestamp: timestamp(task.start),
+            name: self.name,
+            args,
+            task_id: task.span_id,
+        });
+    }
+}
+
+impl<F: Future> Future for ProfiledFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: `future` is never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+        let task = this.task.get_or_insert_with(|| TaskSpan {
+            id: current_thread_id(),
+            span_id: next_span_id(),
+            start: Instant::now(),
+        });
+
+        let poll = {
+            let mut poll_span = ScopedProfiler::start(this.name);
+            poll_span.task_id = task.span_id;
+            // Safety: see above, `future` is pinned along with `self`.
+            unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx)
+        };
+
+        if poll.is_ready() {
+            this.finish(Vec::new());
+        }
+        poll
+    }
+}
+
+impl<F> Drop for ProfiledFuture<F> {
+    fn drop(&mut self) {
+        // Still running, so the task was cancelled.
+        self.finish(vec![Arg::new("cancelled", true)]);
+    }
+}
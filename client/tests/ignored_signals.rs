@@ -0,0 +1,24 @@
+//! Flushing on signals leaves the ones the process ignores alone. Its own
+//! file, since it starts the profiler and changes signal dispositions.
+#![cfg(feature = "enabled")]
+
+use racy_client::ProfilerConfig;
+
+#[test]
+fn ignored_signals_stay_ignored() {
+    let dir = std::env::temp_dir().join(format!("racy-ignored-signals-{}", std::process::id()));
+    unsafe { libc::signal(libc::SIGHUP, libc::SIG_IGN) };
+    let _profiler = ProfilerConfig::new()
+        .output_dir(&dir)
+        .file_name("run.bin")
+        .flush_on_signals(true)
+        .init();
+
+    // Swapping in the default handler hands back the one in place.
+    let hangup = unsafe { libc::signal(libc::SIGHUP, libc::SIG_DFL) };
+    let terminate = unsafe { libc::signal(libc::SIGTERM, libc::SIG_DFL) };
+    assert_eq!(hangup, libc::SIG_IGN);
+    assert_ne!(terminate, libc::SIG_DFL);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,54 @@
+//! A method-level `#[profile]` names its span after the method's Self type.
+//! Its own file, since it starts the profiler.
+#![cfg(feature = "enabled")]
+
+use racy_client::{ProfilerConfig, profile};
+
+struct Parser;
+
+impl Parser {
+    #[profile]
+    fn new() -> Self {
+        Parser
+    }
+
+    #[profile]
+    fn parse(&self) {}
+}
+
+struct Lexer<T>(T);
+
+impl<T> Lexer<T> {
+    #[profile]
+    fn new(input: T) -> Self {
+        Lexer(input)
+    }
+}
+
+#[test]
+fn methods_record_their_self_type() {
+    let dir = std::env::temp_dir().join(format!("racy-method-names-{}", std::process::id()));
+    let profiler = ProfilerConfig::new()
+        .output_dir(&dir)
+        .file_name("run.bin")
+        .init();
+
+    Parser::new().parse();
+    Lexer::new(1u8);
+    Lexer::new("input");
+    drop(profiler);
+
+    let trace = common::read_trace(racy_client::output_path().unwrap().to_path_buf()).unwrap();
+    let mut names: Vec<&str> = trace
+        .events
+        .iter()
+        .map(|event| event.name.as_str())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["Lexer::new", "Lexer::new", "Parser::new", "Parser::parse"]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,35 @@
+//! Threads flush on exit, including ones that reuse the recording buffer of a
+//! thread that already exited. Its own file, since it starts the profiler.
+#![cfg(feature = "enabled")]
+
+use std::thread;
+
+use racy_client::{ProfilerConfig, ScopedProfiler, SpanName, output_path};
+
+#[test]
+fn every_exiting_thread_flushes() {
+    static FIRST: SpanName = SpanName::new("first");
+    static SECOND: SpanName = SpanName::new("second");
+    let dir = std::env::temp_dir().join(format!("racy-thread-exit-{}", std::process::id()));
+    let _profiler = ProfilerConfig::new()
+        .output_dir(&dir)
+        .file_name("run.bin")
+        .init();
+
+    // One after the other, so the second thread is handed the first one's
+    // buffer.
+    for name in [&FIRST, &SECOND] {
+        thread::spawn(move || drop(ScopedProfiler::new(name)))
+            .join()
+            .unwrap();
+
+        let trace = common::read_trace(output_path().unwrap().to_path_buf()).unwrap();
+        assert!(
+            trace.events.iter().any(|event| event.name == name.name()),
+            "{} was not flushed when its thread exited",
+            name.name()
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
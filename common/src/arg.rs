@@ -0,0 +1,168 @@
+use std::{borrow::Cow, fmt, io};
+
+use crate::codec::{ByteReader, write_string};
+
+/// Key/value argument attached to a span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arg {
+    pub key: Cow<'static, str>,
+    pub value: ArgValue,
+}
+
+impl Arg {
+    pub fn new(key: impl Into<Cow<'static, str>>, value: impl Into<ArgValue>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl<K: Into<Cow<'static, str>>, V: Into<ArgValue>> From<(K, V)> for Arg {
+    fn from((key, value): (K, V)) -> Self {
+        Self::new(key, value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(Cow<'static, str>),
+    Bool(bool),
+}
+
+impl ArgValue {
+    /// Name of the value's type, for display next to the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ArgValue::Int(_) => "int",
+            ArgValue::UInt(_) => "uint",
+            ArgValue::Float(_) => "float",
+            ArgValue::Str(_) => "string",
+            ArgValue::Bool(_) => "bool",
+        }
+    }
+}
+
+impl fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgValue::Int(value) => write!(f, "{}", value),
+            ArgValue::UInt(value) => write!(f, "{}", value),
+            ArgValue::Float(value) => write!(f, "{}", value),
+            ArgValue::Str(value) => write!(f, "{}", value),
+            ArgValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+macro_rules! arg_value_from {
+    ($variant:ident as $target:ty: $($ty:ty),*) => {
+        $(
+            impl From<$ty> for ArgValue {
+                fn from(value: $ty) -> Self {
+                    ArgValue::$variant(value as $target)
+                }
+            }
+        )*
+    };
+}
+
+arg_value_from!(Int as i64: i8, i16, i32, i64, isize);
+arg_value_from!(UInt as u64: u8, u16, u32, u64, usize);
+arg_value_from!(Float as f64: f32, f64);
+
+impl From<bool> for ArgValue {
+    fn from(value: bool) -> Self {
+        ArgValue::Bool(value)
+    }
+}
+
+impl From<&'static str> for ArgValue {
+    fn from(value: &'static str) -> Self {
+        ArgValue::Str(Cow::Borrowed(value))
+    }
+}
+
+impl From<String> for ArgValue {
+    fn from(value: String) -> Self {
+        ArgValue::Str(Cow::Owned(value))
+    }
+}
+
+/// Writes up to `u16::MAX` arguments, dropping the rest so the count can't
+/// wrap and throw off every record after this one.
+pub(crate) fn write_args(payload: &mut Vec<u8>, args: &[Arg]) {
+    let args = &args[..args.len().min(u16::MAX as usize)];
+    payload.extend_from_slice(&(args.len() as u16).to_be_bytes());
+    for arg in args {
+        write_string(payload, &arg.key);
+        match &arg.value {
+            ArgValue::Int(value) => {
+                payload.push(0);
+                payload.extend_from_slice(&value.to_be_bytes());
+            }
+            ArgValue::UInt(value) => {
+                payload.push(1);
+                payload.extend_from_slice(&value.to_be_bytes());
+            }
+            ArgValue::Float(value) => {
+                payload.push(2);
+                payload.extend_from_slice(&value.to_bits().to_be_bytes());
+            }
+            ArgValue::Str(value) => {
+                payload.push(3);
+                write_string(payload, value);
+            }
+            ArgValue::Bool(value) => {
+                payload.push(4);
+                payload.push(*value as u8);
+            }
+        }
+    }
+}
+
+pub(crate) fn read_args(payload: &mut ByteReader) -> io::Result<Vec<Arg>> {
+    let count = payload.u16()?;
+    let mut args = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let key = payload.string()?;
+        let value = match payload.u8()? {
+            0 => ArgValue::Int(payload.i64()?),
+            1 => ArgValue::UInt(payload.u64()?),
+            2 => ArgValue::Float(f64::from_bits(payload.u64()?)),
+            3 => ArgValue::Str(Cow::Owned(payload.string()?)),
+            4 => ArgValue::Bool(payload.u8()? != 0),
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown argument type: {}", other),
+                ));
+            }
+        };
+        args.push(Arg {
+            key: Cow::Owned(key),
+            value,
+        });
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::ByteOrder;
+
+    #[test]
+    fn args_past_the_count_limit_are_dropped() {
+        let args = vec![Arg::new("key", true); u16::MAX as usize + 1];
+        let mut payload = Vec::new();
+        write_args(&mut payload, &args);
+
+        let mut reader = ByteReader::new(&payload, ByteOrder::Big);
+        assert_eq!(read_args(&mut reader).unwrap().len(), u16::MAX as usize);
+        assert!(reader.is_empty());
+    }
+}
@@ -0,0 +1,539 @@
+use std::{collections::HashMap, fmt::Write, io};
+
+use crate::{
+    Arg, ArgValue, ClockSource, Counter, Event, FORMAT_VERSION, FlowPhase, Header, InstantScope,
+    Location, Mark, ProcessInfo, ThreadInfo, Trace,
+    json::{self, Value},
+};
+
+/// Renders a trace in the Trace Event Format read by chrome://tracing and
+/// ui.perfetto.dev.
+///
+/// Spans become complete (`X`) events, except for the lifetimes of async
+/// tasks, which become async (`b`/`e`) pairs since they overlap the spans of
+/// whatever thread they finished on. Timestamps are in microseconds since the
+/// earliest record, keeping nanosecond precision in the fraction.
+pub fn serialize_chrome_trace(trace: &Trace) -> String {
+    let start = trace
+        .events
+        .iter()
+        .map(|event| event.timestamp)
+        .chain(trace.marks.iter().map(|mark| mark.timestamp))
+        .chain(trace.counters.iter().map(|counter| counter.timestamp))
+        .chain(trace.flows.iter().map(|flow| flow.timestamp))
+        .min()
+        .unwrap_or(0);
+    let ts = |timestamp: u128| micros(timestamp.saturating_sub(start));
+
+    let mut events = Vec::new();
+
+    for process in &trace.processes {
+        let name = if process.exe_name.is_empty() {
+            format!("Process {}", process.pid)
+        } else {
+            process.exe_name.clone()
+        };
+        events.push(format!(
+            r#"{{"ph":"M","name":"process_name","pid":{},"tid":0,"args":{{"name":{}}}}}"#,
+            process.pid,
+            json::quote(&name)
+        ));
+    }
+
+    for thread in &trace.threads {
+        events.push(format!(
+            r#"{{"ph":"M","name":"thread_name","pid":{},"tid":{},"args":{{"name":{}}}}}"#,
+            thread.pid,
+            thread.id,
+            json::quote(&thread.name)
+        ));
+    }
+
+    for event in &trace.events {
+        let mut args = event.args.clone();
+        if let Some(location) = &event.location {
+            args.push(Arg::new(
+                "location",
+                format!("{}:{}", location.file, location.line),
+            ));
+        }
+        let category = match &event.category {
+            Some(category) => format!(r#","cat":{}"#, json::quote(category)),
+            None => String::new(),
+        };
+        let common = format!(
+            r#""name":{}{},"pid":{},"tid":{},"args":{}"#,
+            json::quote(&event.name),
+            category,
+            event.pid,
+            event.id,
+            json_args(&args)
+        );
+        if event.task_id != 0 && event.task_id == event.span_id {
+            events.push(format!(
+                r#"{{"ph":"b","id":{},"ts":{},{}}}"#,
+                event.span_id,
+                ts(event.timestamp),
+                common
+            ));
+            events.push(format!(
+                r#"{{"ph":"e","id":{},"ts":{},{}}}"#,
+                event.span_id,
+                ts(event.timestamp + event.duration as u128),
+                common
+            ));
+        } else {
+            events.push(format!(
+                r#"{{"ph":"X","ts":{},"dur":{},{}}}"#,
+                ts(event.timestamp),
+                micros(event.duration as u128),
+                common
+            ));
+        }
+    }
+
+    for mark in &trace.marks {
+        let scope = match mark.scope {
+            InstantScope::Thread => "t",
+            InstantScope::Process => "p",
+            InstantScope::Global => "g",
+        };
+        events.push(format!(
+            r#"{{"ph":"i","s":"{}","name":{},"ts":{},"pid":{},"tid":{},"args":{}}}"#,
+            scope,
+            json::quote(&mark.name),
+            ts(mark.timestamp),
+            mark.pid,
+            mark.id,
+            json_args(&mark.args)
+        ));
+    }
+
+    for counter in &trace.counters {
+        events.push(format!(
+            r#"{{"ph":"C","name":{},"ts":{},"pid":{},"tid":{},"args":{{"value":{}}}}}"#,
+            json::quote(&counter.name),
+            ts(counter.timestamp),
+            counter.pid,
+            counter.id,
+            json::number(counter.value)
+        ));
+    }
+
+    for flow in &trace.flows {
+        // Ends bind to the span they were recorded in rather than the next one.
+        let phase = match flow.phase {
+            FlowPhase::Begin => r#""ph":"s""#,
+            FlowPhase::End => r#""ph":"f","bp":"e""#,
+        };
+        events.push(format!(
+            r#"{{{},"name":"flow","cat":"flow","id":{},"ts":{},"pid":{},"tid":{}}}"#,
+            phase,
+            flow.id,
+            ts(flow.timestamp),
+            flow.pid,
+            flow.thread
+        ));
+    }
+
+    let mut output = "{\"displayTimeUnit\":\"ns\",\"traceEvents\":[".to_string();
+    for (index, event) in events.iter().enumerate() {
+        output += if index == 0 { "\n" } else { ",\n" };
+        output += event;
+    }
+    output += "\n]}\n";
+    output
+}
+
+/// Reads a Trace Event Format file, either a bare array of events or an
+/// object holding them in `traceEvents`.
+///
+/// Complete (`X`) events and `B`/`E` pairs become spans, async `b`/`e` pairs
+/// the lifetimes of async tasks, instant events marks, and counter events one
+/// counter per argument; `thread_name` and `process_name` metadata name
+/// threads and processes. Other phases, and spans that never end, are
+/// skipped. The format doesn't link spans to their
+/// parents, so nesting is left to be inferred from time, as for files
+/// recorded before spans had ids.
+pub fn deserialize_chrome_trace(text: &str) -> io::Result<Trace> {
+    let document = json::parse(text)?;
+    let records = match document.get("traceEvents").unwrap_or(&document) {
+        Value::Array(records) => records,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a Chrome trace: expected an array of trace events",
+            ));
+        }
+    };
+
+    let mut trace = Trace::new(Header {
+        version: FORMAT_VERSION,
+        pid: 0,
+        exe_name: String::new(),
+        clock: ClockSource::Realtime,
+        start_time: 0,
+    });
+    let mut ids = Ids::default();
+    // Spans begun but not yet ended, innermost last, per thread.
+    let mut open: HashMap<(u32, u64), Vec<Event>> = HashMap::new();
+    // Likewise for async events, which pair up by category and id instead.
+    let mut open_tasks: HashMap<(u32, String, String), Vec<Event>> = HashMap::new();
+    let mut next_task_id = 1;
+
+    for record in records {
+        let field = |key: &str| record.get(key);
+        let name = field("name").and_then(Value::as_str).unwrap_or_default();
+        let pid = ids.pid(field("pid"));
+        let tid = ids.thread(pid, field("tid"));
+        let timestamp = field("ts").and_then(Value::as_f64).map_or(0, nanos);
+        let mut args = match field("args") {
+            Some(Value::Object(members)) => members
+                .iter()
+                .map(|(key, value)| Arg::new(key.clone(), arg_value(value)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let span = |args: Vec<Arg>| {
+            let mut event = Event {
+                pid,
+                id: tid,
+                timestamp,
+                name: name.to_string(),
+                category: field("cat")
+                    .and_then(Value::as_str)
+                    .filter(|category| !category.is_empty())
+                    .map(str::to_string),
+                args,
+                ..Default::default()
+            };
+            take_location(&mut event);
+            event
+        };
+
+        let task_key = || {
+            (
+                pid,
+                field("cat").map(Value::to_json).unwrap_or_default(),
+                field("id").map(Value::to_json).unwrap_or_default(),
+            )
+        };
+
+        match field("ph").and_then(Value::as_str).unwrap_or_default() {
+            "X" => {
+                let duration = field("dur").and_then(Value::as_f64).map_or(0, nanos);
+                trace.events.push(Event {
+                    duration: duration as u64,
+                    ..span(args)
+                });
+            }
+            "B" => open.entry((pid, tid)).or_default().push(span(args)),
+            "E" => {
+                if let Some(mut event) = open.get_mut(&(pid, tid)).and_then(Vec::pop) {
+                    event.duration = timestamp.saturating_sub(event.timestamp) as u64;
+                    // Arguments may be given at either end.
+                    event.args.append(&mut args);
+                    take_location(&mut event);
+                    trace.events.push(event);
+                }
+            }
+            "b" => {
+                let mut event = span(args);
+                event.span_id = next_task_id;
+                event.task_id = next_task_id;
+                next_task_id += 1;
+                open_tasks.entry(task_key()).or_default().push(event);
+            }
+            "e" => {
+                if let Some(mut event) = open_tasks.get_mut(&task_key()).and_then(Vec::pop) {
+                    event.duration = timestamp.saturating_sub(event.timestamp) as u64;
+                    // Arguments may be given at either end, or repeated at both.
+                    args.retain(|arg| event.args.iter().all(|known| known.key != arg.key));
+                    event.args.append(&mut args);
+                    take_location(&mut event);
+                    trace.events.push(event);
+                }
+            }
+            "i" | "I" => trace.marks.push(Mark {
+                pid,
+                id: tid,
+                timestamp,
+                name: name.to_string(),
+                scope: match field("s").and_then(Value::as_str) {
+                    Some("g") => InstantScope::Global,
+                    Some("p") => InstantScope::Process,
+                    _ => InstantScope::Thread,
+                },
+                args,
+            }),
+            "C" => {
+                let series = args.len();
+                for arg in args {
+                    let value = match arg.value {
+                        ArgValue::Int(value) => value as f64,
+                        ArgValue::UInt(value) => value as f64,
+                        ArgValue::Float(value) => value,
+                        ArgValue::Str(_) | ArgValue::Bool(_) => continue,
+                    };
+                    trace.counters.push(Counter {
+                        pid,
+                        id: tid,
+                        timestamp,
+                        name: if series == 1 {
+                            name.to_string()
+                        } else {
+                            format!("{} {}", name, arg.key)
+                        },
+                        value,
+                    });
+                }
+            }
+            "M" => {
+                let Some(label) = field("args")
+                    .and_then(|args| args.get("name"))
+                    .and_then(Value::as_str)
+                else {
+                    continue;
+                };
+                match name {
+                    "thread_name" => trace.threads.push(ThreadInfo {
+                        pid,
+                        id: tid,
+                        name: label.to_string(),
+                    }),
+                    "process_name" => trace.processes.push(ProcessInfo {
+                        pid,
+                        exe_name: label.to_string(),
+                        command_line: Vec::new(),
+                    }),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Threads some tracers only identify by a string are at least named by it.
+    for (pid, id, name) in ids.named_threads {
+        if !trace
+            .threads
+            .iter()
+            .any(|thread| (thread.pid, thread.id) == (pid, id))
+        {
+            trace.threads.push(ThreadInfo { pid, id, name });
+        }
+    }
+
+    if trace.events.is_empty() && trace.marks.is_empty() && trace.counters.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Chrome trace holds no spans, instant or counter events",
+        ));
+    }
+    Ok(trace)
+}
+
+/// Numeric ids for the pids and tids of a file, which some tracers write as
+/// strings or negative numbers.
+#[derive(Default)]
+struct Ids {
+    assigned: HashMap<String, u64>,
+    named_threads: Vec<(u32, u64, String)>,
+}
+
+impl Ids {
+    fn pid(&mut self, value: Option<&Value>) -> u32 {
+        match value.and_then(Value::as_u64) {
+            Some(pid) if pid <= u32::MAX as u64 => pid as u32,
+            _ => self.assign(value) as u32,
+        }
+    }
+
+    fn thread(&mut self, pid: u32, value: Option<&Value>) -> u64 {
+        if let Some(id) = value.and_then(Value::as_u64) {
+            return id;
+        }
+        let assigned = self.assigned.len();
+        let id = self.assign(value);
+        if self.assigned.len() > assigned
+            && let Some(Value::String(name)) = value
+        {
+            self.named_threads.push((pid, id, name.clone()));
+        }
+        id
+    }
+
+    /// Ids are handed out downwards from the top of the pid range, where they
+    /// are unlikely to meet a numeric one.
+    fn assign(&mut self, value: Option<&Value>) -> u64 {
+        let Some(value) = value else {
+            return 0;
+        };
+        let next = u32::MAX as u64 - self.assigned.len() as u64;
+        *self.assigned.entry(value.to_json()).or_insert(next)
+    }
+}
+
+/// Microseconds, possibly fractional, as whole nanoseconds.
+fn nanos(micros: f64) -> u128 {
+    (micros * 1_000.0).round().max(0.0) as u128
+}
+
+fn arg_value(value: &Value) -> ArgValue {
+    match value {
+        Value::Bool(value) => ArgValue::Bool(*value),
+        Value::Int(value) => ArgValue::Int(*value),
+        Value::UInt(value) => ArgValue::UInt(*value),
+        Value::Float(value) => ArgValue::Float(*value),
+        Value::String(value) => ArgValue::Str(value.clone().into()),
+        // Nested values are kept as their JSON.
+        value => ArgValue::Str(value.to_json().into()),
+    }
+}
+
+/// Turns the `location` argument [`serialize_chrome_trace`] writes back into
+/// the span's location.
+fn take_location(event: &mut Event) {
+    let Some(index) = event.args.iter().position(|arg| arg.key == "location") else {
+        return;
+    };
+    let ArgValue::Str(location) = &event.args[index].value else {
+        return;
+    };
+    if let Some((file, line)) = location.rsplit_once(':')
+        && let Ok(line) = line.parse()
+    {
+        event.location = Some(Location {
+            module_path: String::new(),
+            file: file.to_string(),
+            line,
+        });
+        event.args.remove(index);
+    }
+}
+
+/// Nanoseconds as a decimal number of microseconds.
+fn micros(nanos: u128) -> String {
+    format!("{}.{:03}", nanos / 1_000, nanos % 1_000)
+}
+
+fn json_args(args: &[Arg]) -> String {
+    let mut output = "{".to_string();
+    for (index, arg) in args.iter().enumerate() {
+        let value = match &arg.value {
+            ArgValue::Int(value) => value.to_string(),
+            ArgValue::UInt(value) => value.to_string(),
+            ArgValue::Float(value) => json::number(*value),
+            ArgValue::Str(value) => json::quote(value),
+            ArgValue::Bool(value) => value.to_string(),
+        };
+        let separator = if index == 0 { "" } else { "," };
+        let _ = write!(output, "{}{}:{}", separator, json::quote(&arg.key), value);
+    }
+    output.push('}');
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::sample_events;
+
+    #[test]
+    fn chrome_export_uses_microseconds_since_the_first_record() {
+        let trace = Trace {
+            events: sample_events(),
+            threads: vec![ThreadInfo {
+                pid: 0,
+                id: 1,
+                name: "main \"1\"".to_string(),
+            }],
+            ..Trace::new(Header::current(ClockSource::Realtime))
+        };
+
+        let json = serialize_chrome_trace(&trace);
+        assert!(json.contains(
+            r#"{"ph":"M","name":"thread_name","pid":0,"tid":1,"args":{"name":"main \"1\""}}"#
+        ));
+        assert!(json.contains(r#"{"ph":"X","ts":0.000,"dur":0.010,"name":"outer","cat":"io""#));
+        assert!(json.contains(
+            r#""ts":0.002,"dur":0.005,"name":"inner","pid":0,"tid":1,"args":{"rows":3,"table":"users","location":"src/worker.rs:42"}"#
+        ));
+
+        let imported = deserialize_chrome_trace(&json).unwrap();
+        assert_eq!(imported.threads, trace.threads);
+        assert_eq!(imported.events[1].timestamp, 2);
+        assert_eq!(imported.events[1].duration, 5);
+        assert_eq!(imported.events[1].location.as_ref().unwrap().line, 42);
+        // JSON doesn't say whether a number was signed.
+        assert_eq!(
+            imported.events[1].args,
+            [Arg::new("rows", 3i64), Arg::new("table", "users")]
+        );
+    }
+
+    #[test]
+    fn async_tasks_round_trip() {
+        let task = |span_id, timestamp, duration, name: &str| Event {
+            id: 1,
+            span_id,
+            task_id: 3,
+            timestamp,
+            duration,
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut trace = Trace::new(Header::current(ClockSource::Realtime));
+        trace.events = vec![
+            Event {
+                args: vec![Arg::new("url", "/")],
+                ..task(3, 1_000, 20_000, "fetch")
+            },
+            task(4, 2_000, 1_000, "fetch poll"),
+        ];
+
+        let json = serialize_chrome_trace(&trace);
+        assert!(json.contains(r#"{"ph":"b","id":3,"ts":0.000,"name":"fetch""#));
+
+        let imported = deserialize_chrome_trace(&json).unwrap();
+        let task = imported
+            .events
+            .iter()
+            .find(|event| event.task_id != 0 && event.task_id == event.span_id)
+            .unwrap();
+        assert_eq!(
+            (task.name.as_str(), task.timestamp, task.duration),
+            ("fetch", 0, 20_000)
+        );
+        assert_eq!(task.args, [Arg::new("url", "/")]);
+        assert_eq!(imported.events.len(), 2);
+    }
+
+    #[test]
+    fn chrome_import_pairs_begin_and_end_events() {
+        let json = r#"[
+            {"ph":"M","name":"thread_name","pid":1,"tid":"io","args":{"name":"io thread"}},
+            {"ph":"B","name":"outer","cat":"net","pid":1,"tid":"io","ts":1.5},
+            {"ph":"B","name":"inner","pid":1,"tid":"io","ts":2},
+            {"ph":"E","pid":1,"tid":"io","ts":3,"args":{"bytes":512}},
+            {"ph":"E","pid":1,"tid":"io","ts":10},
+            {"ph":"i","name":"tick","s":"p","pid":1,"tid":7,"ts":4},
+            {"ph":"C","name":"queue","pid":1,"ts":5,"args":{"len":3}},
+            {"ph":"B","name":"unfinished","pid":1,"tid":7,"ts":6}"#;
+
+        let trace = deserialize_chrome_trace(json).unwrap();
+        let spans: Vec<(&str, u128, u64)> = trace
+            .events
+            .iter()
+            .map(|event| (event.name.as_str(), event.timestamp, event.duration))
+            .collect();
+        assert_eq!(spans, [("inner", 2_000, 1_000), ("outer", 1_500, 8_500)]);
+        assert_eq!(trace.events[0].args, [Arg::new("bytes", 512i64)]);
+        assert_eq!(trace.events[1].category.as_deref(), Some("net"));
+        assert_eq!(trace.threads.len(), 1);
+        assert_eq!(trace.threads[0].name, "io thread");
+        assert_eq!(trace.threads[0].id, trace.events[0].id);
+        assert_eq!(trace.marks[0].scope, InstantScope::Process);
+        assert_eq!(trace.counters[0].value, 3.0);
+    }
+}
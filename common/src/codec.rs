@@ -0,0 +1,100 @@
+use std::io;
+
+/// Byte order a file was written in, as announced by its byte order mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+/// Cursor over a byte slice that decodes integers in a fixed byte order.
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    order: ByteOrder,
+}
+
+macro_rules! read_int {
+    ($name:ident, $ty:ty) => {
+        pub fn $name(&mut self) -> io::Result<$ty> {
+            let bytes = self.array()?;
+            Ok(match self.order {
+                ByteOrder::Big => <$ty>::from_be_bytes(bytes),
+                ByteOrder::Little => <$ty>::from_le_bytes(bytes),
+            })
+        }
+    };
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8], order: ByteOrder) -> Self {
+        Self {
+            data,
+            position: 0,
+            order,
+        }
+    }
+
+    pub fn set_order(&mut self, order: ByteOrder) {
+        self.order = order;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.position)
+    }
+
+    pub fn peek(&self, len: usize) -> io::Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Unexpected end of racy data",
+            ));
+        }
+        Ok(&self.data[self.position..self.position + len])
+    }
+
+    pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.peek(len)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    /// Splits off the next `len` bytes as a reader of their own.
+    pub fn slice(&mut self, len: usize) -> io::Result<ByteReader<'a>> {
+        let order = self.order;
+        Ok(ByteReader::new(self.bytes(len)?, order))
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    read_int!(u8, u8);
+    read_int!(u16, u16);
+    read_int!(u32, u32);
+    read_int!(u64, u64);
+    read_int!(u128, u128);
+    read_int!(i64, i64);
+
+    pub fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid UTF-8 in string: {}", e),
+            )
+        })
+    }
+}
+
+pub fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(value.as_bytes());
+}
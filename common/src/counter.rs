@@ -0,0 +1,29 @@
+/// Sample of a numeric time series, such as a queue length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counter {
+    /// Process the sample was recorded in.
+    pub pid: u32,
+    /// Thread the sample was recorded on.
+    pub id: u64,
+    pub timestamp: u128,
+    pub name: String,
+    pub value: f64,
+}
+
+/// Value of a counter sample, convertible from any primitive number.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct CounterValue(pub f64);
+
+macro_rules! counter_value_from {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for CounterValue {
+                fn from(value: $ty) -> Self {
+                    CounterValue(value as f64)
+                }
+            }
+        )*
+    };
+}
+
+counter_value_from!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
@@ -0,0 +1,45 @@
+use std::io;
+
+/// One end of an arrow between two spans, possibly on different threads.
+///
+/// A flow starts where [`FlowPhase::Begin`] was recorded and ends at every
+/// [`FlowPhase::End`] with the same id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flow {
+    /// Id chosen by the application, shared by both ends.
+    pub id: u64,
+    pub phase: FlowPhase,
+    /// Process the end was recorded in, filled in when the file is read.
+    pub pid: u32,
+    /// Thread the end was recorded on.
+    pub thread: u64,
+    /// Span the end was recorded in, 0 outside of any span.
+    pub span_id: u64,
+    pub timestamp: u128,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlowPhase {
+    Begin,
+    End,
+}
+
+impl FlowPhase {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            FlowPhase::Begin => 0,
+            FlowPhase::End => 1,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(FlowPhase::Begin),
+            1 => Ok(FlowPhase::End),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown flow phase: {}", other),
+            )),
+        }
+    }
+}
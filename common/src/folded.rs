@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Trace, child_time, span_parents};
+
+/// Time a line of folded stacks is weighted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldedWeight {
+    /// Time spent in the innermost span itself, which flame graph tools sum
+    /// back up into its callers.
+    SelfTime,
+    /// Whole duration of the innermost span, children included.
+    Total,
+}
+
+/// Renders the spans of a trace as folded stacks, the collapsed format read
+/// by flamegraph.pl and inferno: one `root;child;leaf <nanoseconds>` line per
+/// distinct stack, sorted.
+///
+/// With `per_thread` every stack starts with a frame for its thread, and for
+/// its process too when the trace holds several, instead of merging the
+/// stacks of all threads.
+pub fn serialize_folded(trace: &Trace, weight: FoldedWeight, per_thread: bool) -> String {
+    let parents = span_parents(&trace.events);
+    let child_time = child_time(&trace.events, &parents);
+
+    let thread_names: HashMap<(u32, u64), &str> = trace
+        .threads
+        .iter()
+        .map(|thread| ((thread.pid, thread.id), thread.name.as_str()))
+        .collect();
+    let process_names: HashMap<u32, &str> = trace
+        .processes
+        .iter()
+        .filter(|process| !process.exe_name.is_empty())
+        .map(|process| (process.pid, process.exe_name.as_str()))
+        .collect();
+    let mut pids: Vec<u32> = trace.events.iter().map(|event| event.pid).collect();
+    pids.sort();
+    pids.dedup();
+
+    let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+    for (index, event) in trace.events.iter().enumerate() {
+        let value = match weight {
+            FoldedWeight::SelfTime => event.duration.saturating_sub(child_time[index]),
+            FoldedWeight::Total => event.duration,
+        };
+        if value == 0 {
+            continue;
+        }
+
+        let mut frames = vec![frame(&event.name)];
+        let mut ancestor = parents[index];
+        while let Some(parent) = ancestor {
+            frames.push(frame(&trace.events[parent].name));
+            ancestor = parents[parent];
+        }
+        if per_thread {
+            frames.push(frame(
+                &thread_names
+                    .get(&(event.pid, event.id))
+                    .map_or_else(|| format!("thread {}", event.id), |name| name.to_string()),
+            ));
+            if pids.len() > 1 {
+                frames.push(frame(&match process_names.get(&event.pid) {
+                    Some(name) => format!("{} ({})", name, event.pid),
+                    None => format!("process {}", event.pid),
+                }));
+            }
+        }
+        frames.reverse();
+
+        *stacks.entry(frames.join(";")).or_default() += value;
+    }
+
+    let mut output = String::new();
+    for (stack, value) in stacks {
+        output += &format!("{} {}\n", stack, value);
+    }
+    output
+}
+
+/// A name as a single frame, since `;` separates frames and a line ends the
+/// stack.
+fn frame(name: &str) -> String {
+    name.replace([';', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockSource, Event, Header, ThreadInfo, tests::sample_events};
+
+    #[test]
+    fn folded_stacks_weigh_self_time() {
+        let mut events = sample_events();
+        // The same call tree again, recorded before spans had ids.
+        events.extend(sample_events().into_iter().map(|event| Event {
+            id: 2,
+            span_id: 0,
+            parent_id: 0,
+            ..event
+        }));
+        let trace = Trace {
+            events,
+            threads: vec![ThreadInfo {
+                pid: 0,
+                id: 1,
+                name: "main;1".to_string(),
+            }],
+            ..Trace::new(Header::current(ClockSource::Realtime))
+        };
+
+        assert_eq!(
+            serialize_folded(&trace, FoldedWeight::SelfTime, false),
+            "outer 10\nouter;inner 10\n"
+        );
+        assert_eq!(
+            serialize_folded(&trace, FoldedWeight::Total, true),
+            "main 1;outer 10\nmain 1;outer;inner 5\nthread 2;outer 10\nthread 2;outer;inner 5\n"
+        );
+    }
+}
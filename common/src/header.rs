@@ -0,0 +1,158 @@
+use std::{env, io, process, time::SystemTime};
+
+use crate::codec::{ByteOrder, ByteReader, write_string};
+
+/// Magic bytes every racy file starts with.
+pub const MAGIC: [u8; 4] = *b"RACY";
+
+/// Format generation written by this version of the crate.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Version reported for headerless files written before the header existed.
+pub const LEGACY_VERSION: u16 = 0;
+
+const BYTE_ORDER_MARK: u16 = 0xFEFF;
+
+/// Clock the event timestamps of a file were taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// `SystemTime`, nanoseconds since the UNIX epoch.
+    Realtime,
+    /// A monotonic clock, nanoseconds since the header's `start_time`.
+    Monotonic,
+}
+
+impl ClockSource {
+    fn to_byte(self) -> u8 {
+        match self {
+            ClockSource::Realtime => 0,
+            ClockSource::Monotonic => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(ClockSource::Realtime),
+            1 => Ok(ClockSource::Monotonic),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown clock source: {}", other),
+            )),
+        }
+    }
+}
+
+/// Self-describing header at the start of every racy file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub pid: u32,
+    pub exe_name: String,
+    pub clock: ClockSource,
+    /// Wall-clock time the recording started, in nanoseconds since the UNIX epoch.
+    ///
+    /// This is the anchor monotonic timestamps are relative to.
+    pub start_time: u128,
+}
+
+impl Header {
+    /// Describes the current process, starting now.
+    pub fn current(clock: ClockSource) -> Self {
+        let start_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or(0);
+
+        Self {
+            version: FORMAT_VERSION,
+            pid: process::id(),
+            exe_name: current_exe_name(),
+            clock,
+            start_time,
+        }
+    }
+
+    /// Header assumed for files without one.
+    pub fn legacy() -> Self {
+        Self {
+            version: LEGACY_VERSION,
+            pid: 0,
+            exe_name: String::new(),
+            clock: ClockSource::Realtime,
+            start_time: 0,
+        }
+    }
+}
+
+/// File name of the running executable, empty if it can't be found.
+pub(crate) fn current_exe_name() -> String {
+    env::current_exe()
+        .ok()
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default()
+}
+
+pub fn serialize_header(header: &Header) -> Vec<u8> {
+    let mut fields = Vec::new();
+    fields.extend_from_slice(&header.pid.to_be_bytes());
+    fields.push(header.clock.to_byte());
+    fields.extend_from_slice(&header.start_time.to_be_bytes());
+    write_string(&mut fields, &header.exe_name);
+
+    let mut result = Vec::with_capacity(12 + fields.len());
+    result.extend_from_slice(&MAGIC);
+    result.extend_from_slice(&BYTE_ORDER_MARK.to_be_bytes());
+    result.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    // Length of the versioned fields, so newer writers can append to them.
+    result.extend_from_slice(&(fields.len() as u32).to_be_bytes());
+    result.extend_from_slice(&fields);
+    result
+}
+
+/// Reads the header if `data` starts with one, leaving the reader on the first record.
+///
+/// Returns `None` for legacy headerless files, in which case the reader is untouched.
+pub(crate) fn deserialize_header(reader: &mut ByteReader) -> io::Result<Option<Header>> {
+    if reader.remaining() < MAGIC.len() || reader.peek(MAGIC.len())? != MAGIC {
+        return Ok(None);
+    }
+    reader.bytes(MAGIC.len())?;
+
+    let order = match reader.bytes(2)? {
+        [0xFE, 0xFF] => ByteOrder::Big,
+        [0xFF, 0xFE] => ByteOrder::Little,
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid byte order mark: {:02x?}", other),
+            ));
+        }
+    };
+    reader.set_order(order);
+
+    let version = reader.u16()?;
+    if version == LEGACY_VERSION || version > FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported racy format version: {}", version),
+        ));
+    }
+
+    let fields_len = reader.u32()? as usize;
+    let mut fields = reader.slice(fields_len)?;
+    let pid = fields.u32()?;
+    let clock = ClockSource::from_byte(fields.u8()?)?;
+    let start_time = fields.u128()?;
+    let exe_name = fields.string()?;
+
+    Ok(Some(Header {
+        version,
+        pid,
+        exe_name,
+        clock,
+        start_time,
+    }))
+}
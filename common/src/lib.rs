@@ -1,95 +1,535 @@
 use std::{
-    env, error::Error, fs::{File, OpenOptions}, io::{self, Cursor, Read}, path::PathBuf
+    collections::HashMap,
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{self, Read},
+    path::PathBuf,
 };
 
-const FILE_NAME: &'static str = "racy_output.bin";
+use codec::{ByteOrder, ByteReader};
 
-#[derive(Debug)]
+mod arg;
+mod chrome;
+mod codec;
+mod counter;
+mod flow;
+mod folded;
+mod header;
+mod json;
+mod merge;
+mod output;
+mod process;
+mod record;
+mod stack;
+mod thread;
+
+pub use arg::{Arg, ArgValue};
+pub use chrome::{deserialize_chrome_trace, serialize_chrome_trace};
+pub use counter::{Counter, CounterValue};
+pub use flow::{Flow, FlowPhase};
+pub use folded::{FoldedWeight, serialize_folded};
+pub use header::{ClockSource, FORMAT_VERSION, Header, LEGACY_VERSION, MAGIC, serialize_header};
+pub use json::quote as json_string;
+pub use merge::merge_traces;
+pub use output::{DEFAULT_FILE_TEMPLATE, OUTPUT_ENV, OutputPath, latest_save_filename};
+pub use process::ProcessInfo;
+pub use record::{CounterRecord, MarkRecord, RecordWriter, SpanRecord};
+pub use stack::{child_time, span_parents};
+pub use thread::ThreadInfo;
+
+#[derive(Debug, Default)]
 pub struct Event {
+    /// Process the span was recorded in, 0 if the file doesn't say.
+    pub pid: u32,
+    /// Thread the span was recorded on.
     pub id: u64,
+    /// Process-unique id of the span, 0 for files recorded before spans had ids.
+    pub span_id: u64,
+    /// Id of the span this one was opened in, 0 for root spans.
+    pub parent_id: u64,
+    /// Nesting depth on its thread at capture time.
+    pub depth: u32,
     pub duration: u64,
     pub timestamp: u128,
     pub name: String,
+    /// Where the span was declared, if the client captured it.
+    pub location: Option<Location>,
+    /// Category the span was tagged with, e.g. `#[profile(category = "io")]`.
+    pub category: Option<String>,
+    pub args: Vec<Arg>,
+    /// Span id of the async task this span was polled for, 0 outside of tasks.
+    ///
+    /// The span covering a task's whole lifetime has `task_id == span_id`.
+    pub task_id: u64,
+}
+
+/// Zero-duration event, such as the start of a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mark {
+    /// Process the mark was recorded in.
+    pub pid: u32,
+    /// Thread the mark was recorded on.
+    pub id: u64,
+    pub timestamp: u128,
+    pub name: String,
+    pub scope: InstantScope,
+    pub args: Vec<Arg>,
+}
+
+/// How much of the trace an instant event concerns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum InstantScope {
+    /// Only the thread that recorded it.
+    #[default]
+    Thread,
+    /// Every thread of the recording process.
+    Process,
+    /// Every process in the trace.
+    Global,
 }
 
-pub fn serialize_events(events: &[Event]) -> Vec<u8> {
-    let mut size = 0;
-    for event in events.iter() {
-        size += 28 + event.name.len();
+impl InstantScope {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            InstantScope::Thread => 0,
+            InstantScope::Process => 1,
+            InstantScope::Global => 2,
+        }
     }
-    let mut result = Vec::with_capacity(size);
-
-    for event in events.iter() {
-        result.extend_from_slice(&event.id.to_be_bytes());
-        result.extend_from_slice(&event.timestamp.to_be_bytes());
-        result.extend_from_slice(&event.duration.to_be_bytes());
-        result.extend_from_slice(&(event.name.len() as u32).to_be_bytes());
-        result.extend_from_slice(event.name.as_bytes());
+
+    pub(crate) fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(InstantScope::Thread),
+            1 => Ok(InstantScope::Process),
+            2 => Ok(InstantScope::Global),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown instant scope: {}", other),
+            )),
+        }
     }
-    result
 }
 
-pub fn deserialize_events(data: &[u8]) -> io::Result<Vec<Event>> {
-    let mut cursor = Cursor::new(data);
-    let mut events = Vec::new();
-
-    while cursor.position() < data.len() as u64 {
-        let mut buffer = [0u8; 8];
-        let mut big_buffer = [0u8; 16];
-
-        // Read id
-        cursor.read_exact(&mut buffer)?;
-        let id = u64::from_be_bytes(buffer);
-
-        // Read timestamp
-        cursor.read_exact(&mut big_buffer)?;
-        let timestamp = u128::from_be_bytes(big_buffer);
-
-        // Read duration
-        cursor.read_exact(&mut buffer)?;
-        let duration = u64::from_be_bytes(buffer);
-
-        // Read name length
-        let mut len_buffer = [0u8; 4];
-        cursor.read_exact(&mut len_buffer)?;
-        let name_len = u32::from_be_bytes(len_buffer) as usize;
-
-        // Read name
-        let mut name_buffer = vec![0u8; name_len];
-        cursor.read_exact(&mut name_buffer)?;
-        let name = String::from_utf8(name_buffer).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Invalid UTF-8 in event name: {}", e),
-            )
-        })?;
-
-        events.push(Event {
-            id,
-            timestamp,
-            duration,
-            name,
-        });
+/// Source location of a profiled function or scope.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub module_path: String,
+    pub file: String,
+    pub line: u32,
+}
+
+/// Header and events of a racy file.
+///
+/// Event timestamps are always wall-clock nanoseconds since the UNIX epoch,
+/// whatever clock the file was recorded with.
+#[derive(Debug)]
+pub struct Trace {
+    pub header: Header,
+    pub events: Vec<Event>,
+    pub marks: Vec<Mark>,
+    pub counters: Vec<Counter>,
+    pub flows: Vec<Flow>,
+    pub threads: Vec<ThreadInfo>,
+    pub processes: Vec<ProcessInfo>,
+}
+
+impl Trace {
+    /// A trace with `header` and no records yet.
+    pub fn new(header: Header) -> Self {
+        Self {
+            header,
+            events: Vec::new(),
+            marks: Vec::new(),
+            counters: Vec::new(),
+            flows: Vec::new(),
+            threads: Vec::new(),
+            processes: Vec::new(),
+        }
+    }
+}
+
+/// Serializes a whole file, interning event names into a string table.
+pub fn serialize_trace(trace: &Trace) -> Vec<u8> {
+    let mut writer = RecordWriter::new();
+    let mut strings = HashMap::new();
+    let mut intern = |writer: &mut RecordWriter,
+                      name: &str,
+                      location: Option<&Location>,
+                      category: Option<&str>| {
+        let next_id = strings.len() as u32 + 1;
+        *strings
+            .entry((
+                name.to_string(),
+                location.cloned(),
+                category.map(str::to_string),
+            ))
+            .or_insert_with(|| {
+                writer.name(next_id, name, location, category);
+                next_id
+            })
+    };
+    let timestamp = |timestamp: u128| match trace.header.clock {
+        ClockSource::Realtime => timestamp,
+        ClockSource::Monotonic => timestamp.saturating_sub(trace.header.start_time),
+    };
+
+    // Records belong to the process record before them, so write each
+    // process's records as one run.
+    let mut pids: Vec<u32> = trace
+        .processes
+        .iter()
+        .map(|process| process.pid)
+        .chain(trace.events.iter().map(|event| event.pid))
+        .chain(trace.marks.iter().map(|mark| mark.pid))
+        .chain(trace.counters.iter().map(|counter| counter.pid))
+        .chain(trace.flows.iter().map(|flow| flow.pid))
+        .chain(trace.threads.iter().map(|thread| thread.pid))
+        .collect();
+    pids.sort();
+    pids.dedup();
+
+    for pid in pids {
+        match trace.processes.iter().find(|process| process.pid == pid) {
+            Some(process) => writer.process(process),
+            None => writer.process(&ProcessInfo {
+                pid,
+                ..Default::default()
+            }),
+        }
+
+        for thread in trace.threads.iter().filter(|thread| thread.pid == pid) {
+            writer.thread(thread);
+        }
+
+        for event in trace.events.iter().filter(|event| event.pid == pid) {
+            let name = intern(
+                &mut writer,
+                &event.name,
+                event.location.as_ref(),
+                event.category.as_deref(),
+            );
+            writer.span(&SpanRecord {
+                id: event.id,
+                span_id: event.span_id,
+                parent_id: event.parent_id,
+                depth: event.depth,
+                duration: event.duration,
+                timestamp: timestamp(event.timestamp),
+                name,
+                args: event.args.clone(),
+                task_id: event.task_id,
+            });
+        }
+
+        for mark in trace.marks.iter().filter(|mark| mark.pid == pid) {
+            let name = intern(&mut writer, &mark.name, None, None);
+            writer.mark(&MarkRecord {
+                id: mark.id,
+                timestamp: timestamp(mark.timestamp),
+                name,
+                scope: mark.scope,
+                args: mark.args.clone(),
+            });
+        }
+
+        for counter in trace.counters.iter().filter(|counter| counter.pid == pid) {
+            let name = intern(&mut writer, &counter.name, None, None);
+            writer.counter(&CounterRecord {
+                id: counter.id,
+                timestamp: timestamp(counter.timestamp),
+                name,
+                value: counter.value,
+            });
+        }
+
+        for flow in trace.flows.iter().filter(|flow| flow.pid == pid) {
+            writer.flow(&Flow {
+                timestamp: timestamp(flow.timestamp),
+                ..flow.clone()
+            });
+        }
     }
 
-    Ok(events)
+    let mut result = serialize_header(&trace.header);
+    result.extend(writer.into_bytes());
+    result
 }
 
-pub fn default_save_filename() -> PathBuf {
-    env::temp_dir().join(FILE_NAME)
+pub fn deserialize_trace(data: &[u8]) -> io::Result<Trace> {
+    let mut reader = ByteReader::new(data, ByteOrder::Big);
+
+    match header::deserialize_header(&mut reader)? {
+        Some(header) => {
+            let mut records = record::deserialize_records(&mut reader, header.pid)?;
+            // Files recorded before process records still name their process.
+            if records.processes.is_empty() {
+                records.processes.push(ProcessInfo {
+                    pid: header.pid,
+                    exe_name: header.exe_name.clone(),
+                    command_line: Vec::new(),
+                });
+            }
+            if header.clock == ClockSource::Monotonic {
+                for event in records.events.iter_mut() {
+                    event.timestamp += header.start_time;
+                }
+                for mark in records.marks.iter_mut() {
+                    mark.timestamp += header.start_time;
+                }
+                for counter in records.counters.iter_mut() {
+                    counter.timestamp += header.start_time;
+                }
+                for flow in records.flows.iter_mut() {
+                    flow.timestamp += header.start_time;
+                }
+            }
+            Ok(Trace {
+                header,
+                events: records.events,
+                marks: records.marks,
+                counters: records.counters,
+                flows: records.flows,
+                threads: records.threads,
+                processes: records.processes,
+            })
+        }
+        None => {
+            let events = record::deserialize_legacy_records(&mut reader)?;
+            Ok(Trace {
+                events,
+                ..Trace::new(Header::legacy())
+            })
+        }
+    }
 }
 
-pub fn default_save_file() -> io::Result<File> {
-    OpenOptions::new()
-        .create(true)
-        .append(true)
-        .write(true)
-        .open(default_save_filename())
+pub fn deserialize_events(data: &[u8]) -> io::Result<Vec<Event>> {
+    Ok(deserialize_trace(data)?.events)
 }
 
-pub fn read_events(file: PathBuf) -> Result<Vec<Event>, Box<dyn Error>> {
+pub fn read_trace(file: PathBuf) -> Result<Trace, Box<dyn Error>> {
     let mut file = OpenOptions::new().read(true).open(file)?;
     let mut data = Vec::new();
-    file.read_to_end(&mut data).unwrap();
-    Ok(deserialize_events(&data)?)
-}
\ No newline at end of file
+    file.read_to_end(&mut data)?;
+    Ok(deserialize_trace(&data)?)
+}
+
+pub fn write_trace(file: PathBuf, trace: &Trace) -> Result<(), Box<dyn Error>> {
+    fs::write(file, serialize_trace(trace))?;
+    Ok(())
+}
+
+/// Writes a trace as Trace Event Format JSON, see [`serialize_chrome_trace`].
+pub fn write_chrome_trace(file: PathBuf, trace: &Trace) -> Result<(), Box<dyn Error>> {
+    fs::write(file, serialize_chrome_trace(trace))?;
+    Ok(())
+}
+
+/// Reads a Trace Event Format file, see [`deserialize_chrome_trace`].
+pub fn read_chrome_trace(file: PathBuf) -> Result<Trace, Box<dyn Error>> {
+    Ok(deserialize_chrome_trace(&fs::read_to_string(file)?)?)
+}
+
+/// Writes the spans of a trace as folded stacks, see [`serialize_folded`].
+pub fn write_folded(
+    file: PathBuf,
+    trace: &Trace,
+    weight: FoldedWeight,
+    per_thread: bool,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(file, serialize_folded(trace, weight, per_thread))?;
+    Ok(())
+}
+
+pub fn read_events(file: PathBuf) -> Result<Vec<Event>, Box<dyn Error>> {
+    Ok(read_trace(file)?.events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An outer span and an inner one with a location and arguments.
+    pub(crate) fn sample_events() -> Vec<Event> {
+        vec![
+            Event {
+                id: 1,
+                span_id: 1,
+                duration: 10,
+                timestamp: 1_000,
+                name: "outer".to_string(),
+                category: Some("io".to_string()),
+                ..Default::default()
+            },
+            Event {
+                id: 1,
+                span_id: 2,
+                parent_id: 1,
+                depth: 1,
+                duration: 5,
+                timestamp: 1_002,
+                name: "inner".to_string(),
+                location: Some(Location {
+                    module_path: "app::worker".to_string(),
+                    file: "src/worker.rs".to_string(),
+                    line: 42,
+                }),
+                args: vec![Arg::new("rows", 3u32), Arg::new("table", "users")],
+                task_id: 9,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn trace_round_trips() {
+        for clock in [ClockSource::Realtime, ClockSource::Monotonic] {
+            let mut header = Header::current(clock);
+            header.start_time = 500;
+            let trace = Trace {
+                events: sample_events(),
+                marks: vec![Mark {
+                    pid: 0,
+                    id: 1,
+                    timestamp: 1_004,
+                    name: "frame_begin".to_string(),
+                    scope: InstantScope::Process,
+                    args: vec![Arg::new("frame", 3u32)],
+                }],
+                counters: vec![Counter {
+                    pid: 0,
+                    id: 1,
+                    timestamp: 1_006,
+                    name: "queue_len".to_string(),
+                    value: 12.5,
+                }],
+                flows: vec![Flow {
+                    id: 77,
+                    phase: FlowPhase::End,
+                    pid: 0,
+                    thread: 2,
+                    span_id: 2,
+                    timestamp: 1_003,
+                }],
+                threads: vec![ThreadInfo {
+                    pid: 0,
+                    id: 2,
+                    name: "rayon-worker-3".to_string(),
+                }],
+                ..Trace::new(header.clone())
+            };
+
+            let trace = deserialize_trace(&serialize_trace(&trace)).unwrap();
+            assert_eq!(trace.header, header);
+            assert_eq!(trace.events.len(), 2);
+            assert_eq!(trace.events[1].name, "inner");
+            assert_eq!(trace.events[1].timestamp, 1_002);
+            assert_eq!(trace.events[1].parent_id, 1);
+            assert_eq!(trace.events[1].depth, 1);
+            assert_eq!(trace.events[1].location.as_ref().unwrap().line, 42);
+            assert_eq!(trace.events[0].location, None);
+            assert_eq!(trace.events[0].category.as_deref(), Some("io"));
+            assert_eq!(trace.events[1].category, None);
+            assert_eq!(trace.events[1].args, sample_events()[1].args);
+            assert_eq!(trace.events[1].task_id, 9);
+            assert_eq!(trace.marks[0].name, "frame_begin");
+            assert_eq!(trace.marks[0].timestamp, 1_004);
+            assert_eq!(trace.marks[0].scope, InstantScope::Process);
+            assert_eq!(trace.marks[0].args, vec![Arg::new("frame", 3u32)]);
+            assert_eq!(trace.counters[0].name, "queue_len");
+            assert_eq!(trace.counters[0].timestamp, 1_006);
+            assert_eq!(trace.counters[0].value, 12.5);
+            assert_eq!(trace.flows[0].phase, FlowPhase::End);
+            assert_eq!(trace.flows[0].span_id, 2);
+            assert_eq!(trace.flows[0].timestamp, 1_003);
+            assert_eq!(trace.threads[0].name, "rayon-worker-3");
+        }
+    }
+
+    #[test]
+    fn processes_keep_their_records_apart() {
+        let process = |pid: u32| ProcessInfo {
+            pid,
+            exe_name: format!("worker-{pid}"),
+            command_line: vec![format!("worker-{pid}"), "--fast".to_string()],
+        };
+        let events = [20, 10]
+            .into_iter()
+            .map(|pid| Event {
+                pid,
+                id: 1,
+                name: format!("run-{pid}"),
+                ..Default::default()
+            })
+            .collect();
+        let trace = Trace {
+            events,
+            processes: vec![process(10), process(20)],
+            ..Trace::new(Header::current(ClockSource::Realtime))
+        };
+
+        let trace = deserialize_trace(&serialize_trace(&trace)).unwrap();
+        assert_eq!(trace.processes, vec![process(10), process(20)]);
+        assert_eq!(trace.events.len(), 2);
+        for event in &trace.events {
+            assert_eq!(event.name, format!("run-{}", event.pid));
+        }
+    }
+
+    #[test]
+    fn monotonic_timestamps_are_anchored() {
+        let header = Header::current(ClockSource::Monotonic);
+        let mut writer = RecordWriter::new();
+        writer.string(7, "work");
+        writer.span(&SpanRecord {
+            timestamp: 1_000,
+            name: 7,
+            ..Default::default()
+        });
+        let mut data = serialize_header(&header);
+        data.extend(writer.into_bytes());
+
+        let trace = deserialize_trace(&data).unwrap();
+        assert_eq!(trace.events[0].name, "work");
+        assert_eq!(trace.events[0].timestamp, header.start_time + 1_000);
+    }
+
+    #[test]
+    fn legacy_headerless_file_loads() {
+        let mut data = Vec::new();
+        for event in sample_events() {
+            data.extend_from_slice(&event.id.to_be_bytes());
+            data.extend_from_slice(&event.timestamp.to_be_bytes());
+            data.extend_from_slice(&event.duration.to_be_bytes());
+            codec::write_string(&mut data, &event.name);
+        }
+
+        let trace = deserialize_trace(&data).unwrap();
+        assert_eq!(trace.header.version, LEGACY_VERSION);
+        assert_eq!(trace.events.len(), 2);
+        assert_eq!(trace.events[0].name, "outer");
+        assert_eq!(trace.events[0].span_id, 0);
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut data = serialize_header(&Header::current(ClockSource::Realtime));
+        data[6..8].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+
+        assert!(deserialize_trace(&data).is_err());
+    }
+
+    #[test]
+    fn unknown_records_are_skipped() {
+        let trace = Trace {
+            events: sample_events(),
+            ..Trace::new(Header::current(ClockSource::Realtime))
+        };
+        let mut data = serialize_trace(&trace);
+        data.push(0xEE);
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(&[1, 2, 3]);
+
+        assert_eq!(deserialize_trace(&data).unwrap().events.len(), 2);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    hash::Hash,
+};
+
+use crate::{ClockSource, FORMAT_VERSION, Header, Trace};
+
+/// Combines traces recorded by separate processes into one.
+///
+/// Reading a trace already turns its timestamps into wall-clock time, so the
+/// traces line up on their anchors as they are. Process, thread and flow ids
+/// an earlier trace already took are renumbered, which keeps every thread of
+/// the result distinct even for tools that ignore the process, and stops
+/// flows of processes that picked the same ids from joining up. Names are
+/// interned again when the result is serialized, so shared names are stored
+/// once.
+///
+/// No one process recorded the result, so its header has pid 0 and the exe
+/// name `merged`, and starts at the earliest anchor.
+pub fn merge_traces(traces: impl IntoIterator<Item = Trace>) -> Trace {
+    let mut merged = Trace::new(Header {
+        version: FORMAT_VERSION,
+        pid: 0,
+        exe_name: "merged".to_string(),
+        clock: ClockSource::Realtime,
+        start_time: 0,
+    });
+    let mut start_time = None;
+    let mut taken_pids = BTreeSet::new();
+    let mut taken_threads = BTreeSet::new();
+    let mut taken_flows = BTreeSet::new();
+
+    for trace in traces {
+        // Files written before the header existed have no anchor to offer.
+        if trace.header.start_time != 0 {
+            let anchor = trace.header.start_time;
+            start_time = Some(start_time.map_or(anchor, |start: u128| start.min(anchor)));
+        }
+
+        let pids = assign_ids(
+            &mut taken_pids,
+            trace
+                .processes
+                .iter()
+                .map(|process| process.pid)
+                .chain(trace.events.iter().map(|event| event.pid))
+                .chain(trace.marks.iter().map(|mark| mark.pid))
+                .chain(trace.counters.iter().map(|counter| counter.pid))
+                .chain(trace.flows.iter().map(|flow| flow.pid))
+                .chain(trace.threads.iter().map(|thread| thread.pid))
+                .map(|pid| (pid, pid as u64)),
+        );
+        let threads = assign_ids(
+            &mut taken_threads,
+            trace
+                .events
+                .iter()
+                .map(|event| (event.pid, event.id))
+                .chain(trace.marks.iter().map(|mark| (mark.pid, mark.id)))
+                .chain(
+                    trace
+                        .counters
+                        .iter()
+                        .map(|counter| (counter.pid, counter.id)),
+                )
+                .chain(trace.flows.iter().map(|flow| (flow.pid, flow.thread)))
+                .chain(trace.threads.iter().map(|thread| (thread.pid, thread.id)))
+                .map(|key| (key, key.1)),
+        );
+        // Applications pick flow ids per process.
+        let flows = assign_ids(
+            &mut taken_flows,
+            trace
+                .flows
+                .iter()
+                .map(|flow| ((flow.pid, flow.id), flow.id)),
+        );
+        let pid = |pid: u32| pids[&pid] as u32;
+        let thread = |pid: u32, id: u64| threads[&(pid, id)];
+
+        merged
+            .processes
+            .extend(trace.processes.into_iter().map(|mut process| {
+                process.pid = pid(process.pid);
+                process
+            }));
+        merged
+            .threads
+            .extend(trace.threads.into_iter().map(|mut info| {
+                info.id = thread(info.pid, info.id);
+                info.pid = pid(info.pid);
+                info
+            }));
+        merged
+            .events
+            .extend(trace.events.into_iter().map(|mut event| {
+                event.id = thread(event.pid, event.id);
+                event.pid = pid(event.pid);
+                event
+            }));
+        merged.marks.extend(trace.marks.into_iter().map(|mut mark| {
+            mark.id = thread(mark.pid, mark.id);
+            mark.pid = pid(mark.pid);
+            mark
+        }));
+        merged
+            .counters
+            .extend(trace.counters.into_iter().map(|mut counter| {
+                counter.id = thread(counter.pid, counter.id);
+                counter.pid = pid(counter.pid);
+                counter
+            }));
+        merged.flows.extend(trace.flows.into_iter().map(|mut flow| {
+            flow.id = flows[&(flow.pid, flow.id)];
+            flow.thread = thread(flow.pid, flow.thread);
+            flow.pid = pid(flow.pid);
+            flow
+        }));
+    }
+
+    merged.header.start_time = start_time.unwrap_or(0);
+    merged
+}
+
+/// Maps every key to its id, or to a fresh one if the id is already taken.
+///
+/// Ids that are still free are kept before any are renumbered, so a trace
+/// that collides with nothing comes out unchanged.
+fn assign_ids<K: Ord + Hash + Copy>(
+    taken: &mut BTreeSet<u64>,
+    keys: impl IntoIterator<Item = (K, u64)>,
+) -> HashMap<K, u64> {
+    let keys: BTreeMap<K, u64> = keys.into_iter().collect();
+    let mut assigned = HashMap::new();
+    for (&key, &id) in keys.iter() {
+        if taken.insert(id) {
+            assigned.insert(key, id);
+        }
+    }
+    for &key in keys.keys() {
+        assigned.entry(key).or_insert_with(|| {
+            let id = taken.last().map_or(1, |last| last + 1);
+            taken.insert(id);
+            id
+        });
+    }
+    assigned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Event, Flow, FlowPhase, ProcessInfo, ThreadInfo, deserialize_trace, serialize_trace,
+        tests::sample_events,
+    };
+
+    #[test]
+    fn merged_traces_keep_processes_and_threads_apart() {
+        let run = |start_time: u128| {
+            let mut header = Header::current(ClockSource::Monotonic);
+            header.start_time = start_time;
+            Trace {
+                events: sample_events()
+                    .into_iter()
+                    .map(|event| Event { pid: 7, ..event })
+                    .collect(),
+                flows: [FlowPhase::Begin, FlowPhase::End]
+                    .into_iter()
+                    .map(|phase| Flow {
+                        id: 5,
+                        phase,
+                        pid: 7,
+                        thread: 1,
+                        span_id: 1,
+                        timestamp: 1_001,
+                    })
+                    .collect(),
+                threads: vec![ThreadInfo {
+                    pid: 7,
+                    id: 1,
+                    name: "main".to_string(),
+                }],
+                processes: vec![ProcessInfo {
+                    pid: 7,
+                    ..Default::default()
+                }],
+                ..Trace::new(header)
+            }
+        };
+
+        let merged = merge_traces([run(2_000), run(1_000)]);
+        assert_eq!(merged.header.pid, 0);
+        assert_eq!(merged.header.exe_name, "merged");
+        assert_eq!(merged.header.start_time, 1_000);
+        let pids: Vec<u32> = merged.processes.iter().map(|process| process.pid).collect();
+        assert_eq!(pids, [7, 8]);
+        let threads: Vec<(u32, u64)> = merged
+            .threads
+            .iter()
+            .map(|thread| (thread.pid, thread.id))
+            .collect();
+        assert_eq!(threads, [(7, 1), (8, 2)]);
+        assert!(merged.events[2..].iter().all(|event| event.pid == 8));
+        // Both processes used flow 5, for flows of their own.
+        let flows: Vec<(u32, u64)> = merged
+            .flows
+            .iter()
+            .map(|flow| (flow.pid, flow.id))
+            .collect();
+        assert_eq!(flows, [(7, 5), (7, 5), (8, 6), (8, 6)]);
+
+        let merged = deserialize_trace(&serialize_trace(&merged)).unwrap();
+        assert_eq!(merged.events.len(), 4);
+        assert_eq!(merged.events[3].name, "inner");
+    }
+}
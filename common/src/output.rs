@@ -0,0 +1,137 @@
+use std::{
+    env, fs, io,
+    path::{MAIN_SEPARATOR, Path, PathBuf},
+};
+
+use crate::Header;
+
+/// Environment variable overriding where clients write their files.
+///
+/// A value naming a directory, or ending in a path separator, only moves the
+/// files. Anything else is a path whose file name is used as the template.
+pub const OUTPUT_ENV: &str = "RACY_OUTPUT";
+
+/// File name template used unless one is configured.
+///
+/// `{pid}`, `{exe}` and `{timestamp}` (UNIX seconds at the start of the
+/// recording) are filled in from the file's header, so concurrent runs write
+/// to different files.
+pub const DEFAULT_FILE_TEMPLATE: &str = "racy_{exe}_{pid}_{timestamp}.bin";
+
+/// Directory and file name template clients write to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputPath {
+    pub dir: PathBuf,
+    pub template: String,
+}
+
+impl Default for OutputPath {
+    fn default() -> Self {
+        Self {
+            dir: env::temp_dir(),
+            template: DEFAULT_FILE_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl OutputPath {
+    /// The default output, overridden by `RACY_OUTPUT` if it is set.
+    pub fn from_env() -> Self {
+        match env::var_os(OUTPUT_ENV) {
+            Some(value) if !value.is_empty() => Self::parse(&value.to_string_lossy()),
+            _ => Self::default(),
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        let path = Path::new(value);
+        if value.ends_with(MAIN_SEPARATOR) || value.ends_with('/') || path.is_dir() {
+            return Self {
+                dir: path.to_path_buf(),
+                ..Self::default()
+            };
+        }
+
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let template = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| DEFAULT_FILE_TEMPLATE.to_string());
+        Self { dir, template }
+    }
+
+    /// Path of the file recording the process described by `header`.
+    pub fn file_for(&self, header: &Header) -> PathBuf {
+        let name = self
+            .template
+            .replace("{pid}", &header.pid.to_string())
+            .replace("{exe}", &header.exe_name)
+            .replace(
+                "{timestamp}",
+                &(header.start_time / 1_000_000_000).to_string(),
+            );
+        self.dir.join(name)
+    }
+
+    /// Most recently modified file in `dir` whose name could have come from
+    /// `template`.
+    pub fn latest_file(&self) -> io::Result<PathBuf> {
+        // Only the text around the placeholders is known up front.
+        let prefix = self.template.split('{').next().unwrap_or_default();
+        let suffix = self.template.rsplit('}').next().unwrap_or_default();
+
+        let mut latest = None;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || !name.ends_with(suffix) {
+                continue;
+            }
+            let modified = entry.metadata()?.modified()?;
+            if latest
+                .as_ref()
+                .is_none_or(|(latest_modified, _)| modified > *latest_modified)
+            {
+                latest = Some((modified, entry.path()));
+            }
+        }
+
+        latest.map(|(_, path)| path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No racy files in {}", self.dir.display()),
+            )
+        })
+    }
+}
+
+/// Newest file written with the default or `RACY_OUTPUT` configuration.
+pub fn latest_save_filename() -> io::Result<PathBuf> {
+    OutputPath::from_env().latest_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClockSource;
+
+    #[test]
+    fn output_template_names_files_per_process() {
+        let mut header = Header::current(ClockSource::Monotonic);
+        header.pid = 42;
+        header.exe_name = "server".to_string();
+        header.start_time = 1_700_000_000_123_456_789;
+        let output = OutputPath {
+            dir: PathBuf::from("/traces"),
+            template: "{exe}-{pid}-{timestamp}.bin".to_string(),
+        };
+
+        assert_eq!(
+            output.file_for(&header),
+            PathBuf::from("/traces/server-42-1700000000.bin")
+        );
+    }
+}
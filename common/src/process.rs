@@ -0,0 +1,28 @@
+use std::{env, process};
+
+use crate::header::current_exe_name;
+
+/// Identity of a recorded process.
+///
+/// Records following a process record in a file belong to that process, so a
+/// file merged from several runs holds one before each run's records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub exe_name: String,
+    /// Arguments the process was started with, program name first.
+    pub command_line: Vec<String>,
+}
+
+impl ProcessInfo {
+    /// Describes the current process.
+    pub fn current() -> Self {
+        Self {
+            pid: process::id(),
+            exe_name: current_exe_name(),
+            command_line: env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use std::{collections::HashMap, io};
+
+use crate::{
+    Arg, Counter, Event, Flow, FlowPhase, InstantScope, Location, Mark, ProcessInfo, ThreadInfo,
+    arg::{read_args, write_args},
+    codec::{ByteReader, write_string},
+};
+
+/// Span carrying its name inline, written before names were interned.
+const INLINE_SPAN_RECORD: u8 = 1;
+const STRING_RECORD: u8 = 2;
+const SPAN_RECORD: u8 = 3;
+const MARK_RECORD: u8 = 4;
+const COUNTER_RECORD: u8 = 5;
+const FLOW_RECORD: u8 = 6;
+const THREAD_RECORD: u8 = 7;
+const PROCESS_RECORD: u8 = 8;
+
+/// Span as recorded by a client, naming it through the string table.
+#[derive(Debug, Clone, Default)]
+pub struct SpanRecord {
+    pub id: u64,
+    pub span_id: u64,
+    pub parent_id: u64,
+    pub depth: u32,
+    pub duration: u64,
+    pub timestamp: u128,
+    /// Id of a string previously written with [`RecordWriter::name`].
+    pub name: u32,
+    pub args: Vec<Arg>,
+    /// Span id of the async task this span belongs to, 0 outside of tasks.
+    pub task_id: u64,
+}
+
+/// Instant event as recorded by a client.
+#[derive(Debug, Clone)]
+pub struct MarkRecord {
+    pub id: u64,
+    pub timestamp: u128,
+    /// Id of a string previously written with [`RecordWriter::name`].
+    pub name: u32,
+    pub scope: InstantScope,
+    pub args: Vec<Arg>,
+}
+
+/// Counter sample as recorded by a client.
+#[derive(Debug, Clone)]
+pub struct CounterRecord {
+    pub id: u64,
+    pub timestamp: u128,
+    /// Id of a string previously written with [`RecordWriter::name`].
+    pub name: u32,
+    pub value: f64,
+}
+
+/// Encodes tagged records to be appended after the file header.
+///
+/// Each record is a one byte tag and a `u32` payload length followed by the
+/// payload, so readers can skip record kinds they don't know about.
+#[derive(Default)]
+pub struct RecordWriter {
+    buffer: Vec<u8>,
+}
+
+impl RecordWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Adds `value` to the string table under `id`.
+    pub fn string(&mut self, id: u32, value: &str) {
+        self.name(id, value, None, None);
+    }
+
+    /// Adds a span name to the string table under `id`, along with where the
+    /// span was declared and the category it was tagged with.
+    pub fn name(
+        &mut self,
+        id: u32,
+        value: &str,
+        location: Option<&Location>,
+        category: Option<&str>,
+    ) {
+        self.record(STRING_RECORD, |payload| {
+            payload.extend_from_slice(&id.to_be_bytes());
+            write_string(payload, value);
+            // A category needs a location before it, an empty one stands for none.
+            if location.is_some() || category.is_some() {
+                let location = location.cloned().unwrap_or_default();
+                write_string(payload, &location.module_path);
+                write_string(payload, &location.file);
+                payload.extend_from_slice(&location.line.to_be_bytes());
+            }
+            if let Some(category) = category {
+                write_string(payload, category);
+            }
+        });
+    }
+
+    pub fn span(&mut self, span: &SpanRecord) {
+        self.record(SPAN_RECORD, |payload| {
+            payload.extend_from_slice(&span.id.to_be_bytes());
+            payload.extend_from_slice(&span.timestamp.to_be_bytes());
+            payload.extend_from_slice(&span.duration.to_be_bytes());
+            payload.extend_from_slice(&span.name.to_be_bytes());
+            payload.extend_from_slice(&span.span_id.to_be_bytes());
+            payload.extend_from_slice(&span.parent_id.to_be_bytes());
+            payload.extend_from_slice(&span.depth.to_be_bytes());
+            // Optional trailing fields, each implying the ones before it.
+            if !span.args.is_empty() || span.task_id != 0 {
+                write_args(payload, &span.args);
+            }
+            if span.task_id != 0 {
+                payload.extend_from_slice(&span.task_id.to_be_bytes());
+            }
+        });
+    }
+
+    pub fn mark(&mut self, mark: &MarkRecord) {
+        self.record(MARK_RECORD, |payload| {
+            payload.extend_from_slice(&mark.id.to_be_bytes());
+            payload.extend_from_slice(&mark.timestamp.to_be_bytes());
+            payload.extend_from_slice(&mark.name.to_be_bytes());
+            payload.push(mark.scope.to_byte());
+            if !mark.args.is_empty() {
+                write_args(payload, &mark.args);
+            }
+        });
+    }
+
+    pub fn counter(&mut self, counter: &CounterRecord) {
+        self.record(COUNTER_RECORD, |payload| {
+            payload.extend_from_slice(&counter.id.to_be_bytes());
+            payload.extend_from_slice(&counter.timestamp.to_be_bytes());
+            payload.extend_from_slice(&counter.name.to_be_bytes());
+            payload.extend_from_slice(&counter.value.to_bits().to_be_bytes());
+        });
+    }
+
+    pub fn flow(&mut self, flow: &Flow) {
+        self.record(FLOW_RECORD, |payload| {
+            payload.extend_from_slice(&flow.id.to_be_bytes());
+            payload.push(flow.phase.to_byte());
+            payload.extend_from_slice(&flow.thread.to_be_bytes());
+            payload.extend_from_slice(&flow.span_id.to_be_bytes());
+            payload.extend_from_slice(&flow.timestamp.to_be_bytes());
+        });
+    }
+
+    pub fn thread(&mut self, thread: &ThreadInfo) {
+        self.record(THREAD_RECORD, |payload| {
+            payload.extend_from_slice(&thread.id.to_be_bytes());
+            write_string(payload, &thread.name);
+        });
+    }
+
+    /// Starts the records of `process`, everything written after it belongs
+    /// to it until the next process record.
+    pub fn process(&mut self, process: &ProcessInfo) {
+        self.record(PROCESS_RECORD, |payload| {
+            payload.extend_from_slice(&process.pid.to_be_bytes());
+            write_string(payload, &process.exe_name);
+            payload.extend_from_slice(&(process.command_line.len() as u32).to_be_bytes());
+            for arg in &process.command_line {
+                write_string(payload, arg);
+            }
+        });
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    fn record(&mut self, tag: u8, write: impl FnOnce(&mut Vec<u8>)) {
+        self.buffer.push(tag);
+        let len_position = self.buffer.len();
+        self.buffer.extend_from_slice(&0u32.to_be_bytes());
+        write(&mut self.buffer);
+        let len = (self.buffer.len() - len_position - 4) as u32;
+        self.buffer[len_position..len_position + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// Everything following the header of a file.
+#[derive(Default)]
+pub(crate) struct Records {
+    pub events: Vec<Event>,
+    pub marks: Vec<Mark>,
+    pub counters: Vec<Counter>,
+    pub flows: Vec<Flow>,
+    pub threads: Vec<ThreadInfo>,
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Decodes tagged records, resolving interned names once everything is read.
+///
+/// Records before the first process record are attributed to `pid`.
+pub(crate) fn deserialize_records(reader: &mut ByteReader, mut pid: u32) -> io::Result<Records> {
+    let mut events = Vec::new();
+    let mut marks = Vec::new();
+    let mut counters = Vec::new();
+    let mut flows = Vec::new();
+    let mut threads = Vec::new();
+    let mut processes = Vec::new();
+    let mut strings = HashMap::new();
+    let mut unresolved = Vec::new();
+    let mut unresolved_marks = Vec::new();
+    let mut unresolved_counters = Vec::new();
+
+    while !reader.is_empty() {
+        let tag = reader.u8()?;
+        let len = reader.u32()? as usize;
+        let mut payload = reader.slice(len)?;
+
+        // Unknown record kinds come from newer writers and are skipped.
+        match tag {
+            INLINE_SPAN_RECORD => events.push(Event {
+                pid,
+                ..read_inline_span(&mut payload)?
+            }),
+            STRING_RECORD => {
+                let id = payload.u32()?;
+                let value = payload.string()?;
+                let location = if payload.is_empty() {
+                    None
+                } else {
+                    Some(Location {
+                        module_path: payload.string()?,
+                        file: payload.string()?,
+                        line: payload.u32()?,
+                    })
+                    .filter(|location| *location != Location::default())
+                };
+                let category = if payload.is_empty() {
+                    None
+                } else {
+                    Some(payload.string()?)
+                };
+                strings.insert(id, (value, location, category));
+            }
+            SPAN_RECORD => {
+                let (event, name) = read_span(&mut payload)?;
+                unresolved.push((events.len(), name));
+                events.push(Event { pid, ..event });
+            }
+            MARK_RECORD => {
+                let (mark, name) = read_mark(&mut payload)?;
+                unresolved_marks.push((marks.len(), name));
+                marks.push(Mark { pid, ..mark });
+            }
+            COUNTER_RECORD => {
+                let id = payload.u64()?;
+                let timestamp = payload.u128()?;
+                let name = payload.u32()?;
+                unresolved_counters.push((counters.len(), name));
+                counters.push(Counter {
+                    pid,
+                    id,
+                    timestamp,
+                    name: String::new(),
+                    value: f64::from_bits(payload.u64()?),
+                });
+            }
+            FLOW_RECORD => flows.push(Flow {
+                id: payload.u64()?,
+                phase: FlowPhase::from_byte(payload.u8()?)?,
+                pid,
+                thread: payload.u64()?,
+                span_id: payload.u64()?,
+                timestamp: payload.u128()?,
+            }),
+            THREAD_RECORD => threads.push(ThreadInfo {
+                pid,
+                id: payload.u64()?,
+                name: payload.string()?,
+            }),
+            PROCESS_RECORD => {
+                let process = read_process(&mut payload)?;
+                pid = process.pid;
+                processes.push(process);
+            }
+            _ => {}
+        }
+    }
+
+    let lookup = |id: u32| {
+        strings.get(&id).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Record refers to unknown string id {}", id),
+            )
+        })
+    };
+    for (index, name) in unresolved {
+        let (name, location, category) = lookup(name)?;
+        events[index].name = name;
+        events[index].location = location;
+        events[index].category = category;
+    }
+    for (index, name) in unresolved_marks {
+        marks[index].name = lookup(name)?.0;
+    }
+    for (index, name) in unresolved_counters {
+        counters[index].name = lookup(name)?.0;
+    }
+
+    Ok(Records {
+        events,
+        marks,
+        counters,
+        flows,
+        threads,
+        processes,
+    })
+}
+
+fn read_process(payload: &mut ByteReader) -> io::Result<ProcessInfo> {
+    let pid = payload.u32()?;
+    let exe_name = payload.string()?;
+    let len = payload.u32()?;
+    let command_line = (0..len)
+        .map(|_| payload.string())
+        .collect::<io::Result<_>>()?;
+    Ok(ProcessInfo {
+        pid,
+        exe_name,
+        command_line,
+    })
+}
+
+fn read_mark(payload: &mut ByteReader) -> io::Result<(Mark, u32)> {
+    let id = payload.u64()?;
+    let timestamp = payload.u128()?;
+    let name = payload.u32()?;
+    let mut mark = Mark {
+        pid: 0,
+        id,
+        timestamp,
+        name: String::new(),
+        scope: InstantScope::from_byte(payload.u8()?)?,
+        args: Vec::new(),
+    };
+    if !payload.is_empty() {
+        mark.args = read_args(payload)?;
+    }
+    Ok((mark, name))
+}
+
+fn read_span(payload: &mut ByteReader) -> io::Result<(Event, u32)> {
+    let id = payload.u64()?;
+    let timestamp = payload.u128()?;
+    let duration = payload.u64()?;
+    let name = payload.u32()?;
+    let mut event = Event {
+        id,
+        timestamp,
+        duration,
+        span_id: payload.u64()?,
+        parent_id: payload.u64()?,
+        depth: payload.u32()?,
+        ..Default::default()
+    };
+    if !payload.is_empty() {
+        event.args = read_args(payload)?;
+    }
+    if !payload.is_empty() {
+        event.task_id = payload.u64()?;
+    }
+    Ok((event, name))
+}
+
+fn read_inline_span(payload: &mut ByteReader) -> io::Result<Event> {
+    let mut event = Event {
+        id: payload.u64()?,
+        timestamp: payload.u128()?,
+        duration: payload.u64()?,
+        name: payload.string()?,
+        ..Default::default()
+    };
+    // Call-tree linkage, absent from the earliest inline spans.
+    if !payload.is_empty() {
+        event.span_id = payload.u64()?;
+        event.parent_id = payload.u64()?;
+        event.depth = payload.u32()?;
+    }
+    Ok(event)
+}
+
+/// Reads the bare records written before files had a header.
+pub(crate) fn deserialize_legacy_records(reader: &mut ByteReader) -> io::Result<Vec<Event>> {
+    let mut events = Vec::new();
+
+    while !reader.is_empty() {
+        let id = reader.u64()?;
+        let timestamp = reader.u128()?;
+        let duration = reader.u64()?;
+        let name = reader.string()?;
+
+        events.push(Event {
+            id,
+            timestamp,
+            duration,
+            name,
+            ..Default::default()
+        });
+    }
+
+    Ok(events)
+}
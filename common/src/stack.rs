@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use crate::Event;
+
+/// Index of each span's parent in `events`, `None` for roots.
+///
+/// Spans recorded with ids name their parent; for older ones the parent is
+/// the innermost span of the same thread still open when they started.
+pub fn span_parents(events: &[Event]) -> Vec<Option<usize>> {
+    let mut parents = vec![None; events.len()];
+
+    let by_id: HashMap<(u32, u64), usize> = events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| event.span_id != 0)
+        .map(|(index, event)| ((event.pid, event.span_id), index))
+        .collect();
+    for (index, event) in events.iter().enumerate() {
+        if event.span_id != 0 && event.parent_id != 0 {
+            parents[index] = by_id.get(&(event.pid, event.parent_id)).copied();
+        }
+    }
+
+    let mut unlinked: Vec<usize> = (0..events.len())
+        .filter(|&index| events[index].span_id == 0)
+        .collect();
+    unlinked.sort_by(|&a, &b| {
+        let (a, b) = (&events[a], &events[b]);
+        (a.pid, a.id, a.timestamp)
+            .cmp(&(b.pid, b.id, b.timestamp))
+            .then(b.duration.cmp(&a.duration))
+    });
+    let end = |event: &Event| event.timestamp + event.duration as u128;
+    let mut stack: Vec<usize> = Vec::new();
+    for index in unlinked {
+        let event = &events[index];
+        while let Some(&open) = stack.last() {
+            let open = &events[open];
+            if (open.pid, open.id) == (event.pid, event.id) && end(open) > event.timestamp {
+                break;
+            }
+            stack.pop();
+        }
+        parents[index] = stack.last().copied();
+        stack.push(index);
+    }
+
+    parents
+}
+
+/// Time each span spent in its direct children, given the parents
+/// [`span_parents`] found.
+pub fn child_time(events: &[Event], parents: &[Option<usize>]) -> Vec<u64> {
+    let mut child_time = vec![0; events.len()];
+    for (index, parent) in parents.iter().enumerate() {
+        if let Some(parent) = *parent {
+            child_time[parent] += events[index].duration;
+        }
+    }
+    child_time
+}
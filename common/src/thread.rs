@@ -0,0 +1,10 @@
+/// Metadata a client records once for every thread it sees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadInfo {
+    /// Process the thread belongs to, filled in when the file is read.
+    pub pid: u32,
+    /// Id the thread's spans, marks and counters are recorded under.
+    pub id: u64,
+    /// The thread's name, or its OS thread id if it has none.
+    pub name: String,
+}
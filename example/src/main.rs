@@ -1,4 +1,6 @@
-use racy_client::{init_profiler, profile};
+use racy_client::{
+    InstantScope, ProfilerConfig, flow_begin, flow_end, mark, mark_with, profile, profile_scope,
+};
 
 use rayon::prelude::*;
 use std::sync::Arc;
@@ -12,7 +14,7 @@ fn cpu_intensive_work(n: u64) -> u64 {
     let mut sum = 0;
     for i in 0..n {
         sum += i * i;
-        sum %= 1000_000_007;
+        sum %= 1_000_000_007;
         // Simulate some work
         if i % 10000 == 0 {
             thread::sleep(Duration::from_micros(1));
@@ -21,7 +23,7 @@ fn cpu_intensive_work(n: u64) -> u64 {
     sum
 }
 
-#[profile]
+#[profile(category = "io")]
 fn io_simulation(duration_ms: u64) {
     thread::sleep(Duration::from_millis(duration_ms));
 }
@@ -37,8 +39,28 @@ fn memory_work(size: usize) -> Vec<i32> {
     vec
 }
 
+struct Accumulator {
+    total: u64,
+}
+
+#[profile(category = "math")]
+impl Accumulator {
+    fn new() -> Self {
+        Self { total: 0 }
+    }
+
+    #[profile(sample = 10)]
+    fn add(&mut self, values: &[i32]) {
+        self.total += values.iter().map(|&x| x as u64).sum::<u64>();
+    }
+}
+
 fn main() {
-    init_profiler();
+    let _profiler = ProfilerConfig::new().flush_on_signals(true).init();
+    rayon::ThreadPoolBuilder::new()
+        .thread_name(|index| format!("rayon-worker-{index}"))
+        .build_global()
+        .unwrap();
     println!("=== Multi-threaded Profiling Test with Rayon ===\n");
 
     // Test 1: Parallel computation
@@ -52,10 +74,28 @@ fn main() {
     // Test 2: Parallel I/O simulation
     println!("2. Parallel I/O simulation:");
     let io_times: Vec<u64> = vec![100, 200, 150, 80, 300, 120];
+    mark_with(
+        "io_phase",
+        InstantScope::Process,
+        [("batches", io_times.len())],
+    );
+
+    {
+        profile_scope!("dispatch_io");
+        for batch in 0..io_times.len() as u64 {
+            flow_begin(batch);
+        }
+    }
 
     io_times
         .par_iter()
-        .for_each(|&duration| io_simulation(duration));
+        .enumerate()
+        .for_each(|(batch, &duration)| {
+            profile_scope!("io_batch", duration_ms = duration, simulated = true);
+            flow_end(batch as u64);
+            io_simulation(duration);
+            mark("io_done");
+        });
 
     println!();
 
@@ -63,18 +103,21 @@ fn main() {
     println!("3. Mixed workload:");
     let counter = Arc::new(AtomicU64::new(0));
 
-    (0..10).into_par_iter().for_each(|i| {
+    (0..10).into_par_iter().for_each(|_i| {
         // Some CPU work
         let _result = cpu_intensive_work(10000);
 
         // Some memory work
-        let _vec = memory_work(1000);
+        let vec = memory_work(1000);
+        let mut accumulator = Accumulator::new();
+        accumulator.add(&vec);
 
         // Some I/O simulation
         io_simulation(50);
 
         // Update shared counter
-        counter.fetch_add(1, Ordering::Relaxed);
+        let completed = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        racy_client::counter("completed_tasks", completed);
     });
 
     println!("Counter final value: {}\n", counter.load(Ordering::Relaxed));
@@ -110,11 +153,12 @@ fn main() {
     println!("5. Custom thread pool size test:");
     let custom_pool = rayon::ThreadPoolBuilder::new()
         .num_threads(3)
+        .thread_name(|index| format!("custom-pool-{index}"))
         .build()
         .unwrap();
 
     custom_pool.install(|| {
-        (0..6).into_par_iter().for_each(|i| {
+        (0..6).into_par_iter().for_each(|_i| {
             cpu_intensive_work(25000);
         });
     });
@@ -126,6 +170,22 @@ fn main() {
 mod tests {
     use super::*;
 
+    #[profile(name = "parse", if = cfg!(debug_assertions))]
+    async fn parse_doubled(text: &str) -> Result<u32, std::num::ParseIntError> {
+        let value = text.trim().parse::<u32>()?;
+        Ok(value * 2)
+    }
+
+    #[test]
+    fn test_profiled_async_fn() {
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        let mut future = std::pin::pin!(parse_doubled(" 21 "));
+        assert_eq!(
+            future.as_mut().poll(&mut cx),
+            std::task::Poll::Ready(Ok(42))
+        );
+    }
+
     #[test]
     fn test_profiler_in_parallel() {
         let results: Vec<u64> = (1..=4)
@@ -5,13 +5,18 @@
 // syn = { version = "2.0", features = ["full"] }
 
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{ItemFn, parse_macro_input};
+use quote::{quote, quote_spanned};
+use syn::{
+    Block, Expr, Ident, ImplItem, Item, ItemImpl, LitStr, Meta, Signature, Token, Type,
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+};
 
 /// Adds profiling to a function by inserting a ScopedProfiler at the beginning
 ///
 /// Usage:
-/// ```
+/// ```ignore
 /// #[profile]
 /// pub fn my_function() {
 ///     // Your code here
@@ -19,30 +24,344 @@ use syn::{ItemFn, parse_macro_input};
 /// ```
 ///
 /// This will transform the function to:
-/// ```
+/// ```ignore
 /// pub fn my_function() {
-///     let _profiler = ScopedProfiler::new("my_function");
+///     let _profiler = {
+///         static NAME: SpanName =
+///             SpanName::with_location("my_function", module_path!(), file!(), line!());
+///         ScopedProfiler::new(&NAME)
+///     };
 ///     // Your original code here
 /// }
 /// ```
+///
+/// On an `async fn` each poll is recorded as its own span on the polling
+/// thread, linked to a span covering the whole task (see `ProfiledFuture`).
+///
+/// On an impl block every method is profiled and named after the impl's Self
+/// type, so `#[profile] impl Parser { fn new() .. }` records `Parser::new`.
+///
+/// A single method can't see its impl block, so its span is named after the
+/// Self type at runtime instead, dropping the module path and generic
+/// arguments. That needs the signature to mention `Self`, through a `self`
+/// receiver or `Self` in an argument or the return type. Other associated
+/// functions are recorded under their bare name.
+///
+/// Arguments, all optional:
+/// ```ignore
+/// #[profile(name = "load", category = "io", sample = 100, if = cfg!(debug_assertions))]
+/// ```
+/// - `name` replaces the function name. On an impl block it replaces the type
+///   name instead, giving `load::new`.
+/// - `category` tags the span, and the viewer colours spans by category.
+/// - `sample = N` records only the first of every N calls.
+/// - `if = expr` records a call only when `expr` is true.
+///
+/// Methods of a profiled impl block may carry their own `#[profile(..)]` to
+/// override the block's arguments.
+///
+/// Without racy-client's `enabled` feature the arguments are still checked but
+/// the item is emitted unchanged.
 #[proc_macro_attribute]
-pub fn profile(_args: TokenStream, input: TokenStream) -> TokenStream {
-    let input_fn = parse_macro_input!(input as ItemFn);
-
-    let fn_name = &input_fn.sig.ident;
-    let fn_name_str = fn_name.to_string();
-    let vis = &input_fn.vis;
-    let sig = &input_fn.sig;
-    let block = &input_fn.block;
-    let attrs = &input_fn.attrs;
-
-    let expanded = quote! {
-        #(#attrs)*
-        #vis #sig {
-            let _profiler = racy_client::ScopedProfiler::new(#fn_name_str);
-            #block
+pub fn profile(args: TokenStream, input: TokenStream) -> TokenStream {
+    TokenStream::from(expand(args.into(), input.into(), cfg!(feature = "enabled")))
+}
+
+fn expand(
+    args: proc_macro2::TokenStream,
+    input: proc_macro2::TokenStream,
+    enabled: bool,
+) -> proc_macro2::TokenStream {
+    let args: ProfileArgs = match syn::parse2(args) {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error(),
+    };
+    let item: Item = match syn::parse2(input) {
+        Ok(item) => item,
+        Err(err) => return err.to_compile_error(),
+    };
+
+    match item {
+        Item::Fn(mut input_fn) => {
+            if enabled {
+                let name = match &args.name {
+                    Some(name) => Naming::Fixed(name.value()),
+                    None if mentions_self(&input_fn.sig) => {
+                        Naming::Method(input_fn.sig.ident.to_string())
+                    }
+                    None => Naming::Fixed(input_fn.sig.ident.to_string()),
+                };
+                *input_fn.block = instrument(name, &args, &input_fn.sig, &input_fn.block);
+            }
+            quote! { #input_fn }
+        }
+        Item::Impl(item_impl) => {
+            instrument_impl(item_impl, args, enabled).unwrap_or_else(|err| err.to_compile_error())
         }
+        other => syn::Error::new(
+            other.span(),
+            "#[profile] can only be applied to functions and impl blocks",
+        )
+        .to_compile_error(),
+    }
+}
+
+/// Arguments given to `#[profile(..)]`.
+#[derive(Default, Clone)]
+struct ProfileArgs {
+    name: Option<LitStr>,
+    category: Option<LitStr>,
+    sample: Option<u32>,
+    condition: Option<Expr>,
+}
+
+impl Parse for ProfileArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+
+        while !input.is_empty() {
+            // `if` is a keyword, so it can't be parsed as a plain identifier.
+            let key = Ident::parse_any(input)?;
+            input.parse::<Token![=]>()?;
+
+            let duplicate = match key.to_string().as_str() {
+                "name" => args.name.replace(input.parse()?).is_some(),
+                "category" => args.category.replace(input.parse()?).is_some(),
+                "sample" => {
+                    let every: syn::LitInt = input.parse()?;
+                    let value = every.base10_parse::<u32>()?;
+                    if value == 0 {
+                        return Err(syn::Error::new(every.span(), "`sample` must be at least 1"));
+                    }
+                    args.sample.replace(value).is_some()
+                }
+                "if" => args.condition.replace(input.parse()?).is_some(),
+                other => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!(
+                            "unknown #[profile] argument `{}`, expected `name`, `category`, `sample` or `if`",
+                            other
+                        ),
+                    ));
+                }
+            };
+            if duplicate {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!("duplicate #[profile] argument `{}`", key),
+                ));
+            }
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        Ok(args)
+    }
+}
+
+impl ProfileArgs {
+    /// Arguments of a method's own #[profile], falling back to the impl's.
+    fn with_overrides(&self, overrides: ProfileArgs) -> ProfileArgs {
+        ProfileArgs {
+            name: overrides.name,
+            category: overrides.category.or_else(|| self.category.clone()),
+            sample: overrides.sample.or(self.sample),
+            condition: overrides.condition.or_else(|| self.condition.clone()),
+        }
+    }
+
+    /// Condition a call has to meet to be recorded, if any.
+    fn gate(&self) -> Option<proc_macro2::TokenStream> {
+        // The condition goes first so sampling only counts enabled calls.
+        match (&self.condition, self.sample) {
+            (None, None) => None,
+            (Some(condition), None) => Some(quote! { (#condition) }),
+            (None, Some(_)) => Some(quote! { NAME.sample() }),
+            (Some(condition), Some(_)) => Some(quote! { (#condition) && NAME.sample() }),
+        }
+    }
+}
+
+fn instrument_impl(
+    mut item_impl: ItemImpl,
+    args: ProfileArgs,
+    enabled: bool,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let self_type = match &args.name {
+        Some(name) => name.value(),
+        None => type_name(&item_impl.self_ty),
     };
 
-    TokenStream::from(expanded)
+    for item in item_impl.items.iter_mut() {
+        if let ImplItem::Fn(method) = item {
+            // A const fn can't touch the profiler.
+            if method.sig.constness.is_some() {
+                continue;
+            }
+
+            // A method's own #[profile] would profile it a second time, so it
+            // is folded into this one.
+            let mut method_args = ProfileArgs::default();
+            for attr in method.attrs.iter().filter(|attr| is_profile_attr(attr)) {
+                if let Meta::List(_) = attr.meta {
+                    method_args = attr.parse_args()?;
+                }
+            }
+            method.attrs.retain(|attr| !is_profile_attr(attr));
+            if !enabled {
+                continue;
+            }
+
+            let method_args = args.with_overrides(method_args);
+            let name = match &method_args.name {
+                Some(name) => Naming::Fixed(name.value()),
+                None => Naming::Fixed(format!("{}::{}", self_type, method.sig.ident)),
+            };
+            method.block = instrument(name, &method_args, &method.sig, &method.block);
+        }
+    }
+
+    Ok(quote! { #item_impl })
+}
+
+/// How a span gets its name.
+enum Naming {
+    /// Known at compile time.
+    Fixed(String),
+    /// The method's name, prefixed with its Self type at runtime.
+    Method(String),
+}
+
+/// Wraps `block` so it runs inside a span called `name`.
+///
+/// The body of an `async fn` becomes a `ProfiledFuture` instead, since a guard
+/// would only time building the future or be held across awaits.
+fn instrument(name: Naming, args: &ProfileArgs, sig: &Signature, block: &Block) -> Block {
+    let (name, constructor) = match name {
+        Naming::Fixed(name) => (name, quote! { new }),
+        Naming::Method(name) => (name, quote! { method::<Self> }),
+    };
+    let category = args
+        .category
+        .as_ref()
+        .map(|category| quote! { .with_category(#category) });
+    let sample = args.sample.map(|every| quote! { .sampled(#every) });
+
+    // Spanned to the block so `line!()` reports where the function body starts.
+    let span_name = quote_spanned! {block.span()=>
+        static NAME: racy_client::SpanName =
+            racy_client::SpanName::with_location(#name, module_path!(), file!(), line!())
+                #category #sample;
+    };
+
+    match (sig.asyncness.is_some(), args.gate()) {
+        (true, None) => syn::parse_quote! {{
+            #span_name
+            racy_client::ProfiledFuture::#constructor(&NAME, async move #block).await
+        }},
+        (true, Some(gate)) => syn::parse_quote! {{
+            #span_name
+            let future = async move #block;
+            if #gate {
+                racy_client::ProfiledFuture::#constructor(&NAME, future).await
+            } else {
+                future.await
+            }
+        }},
+        (false, None) => syn::parse_quote! {{
+            let _profiler = {
+                #span_name
+                racy_client::ScopedProfiler::#constructor(&NAME)
+            };
+            #block
+        }},
+        (false, Some(gate)) => syn::parse_quote! {{
+            let _profiler = {
+                #span_name
+                (#gate).then(|| racy_client::ScopedProfiler::#constructor(&NAME))
+            };
+            #block
+        }},
+    }
+}
+
+/// Renders a type the way it is written in source, without token spacing.
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(' ', "")
+}
+
+/// Whether a signature uses `self` or `Self`, so it belongs to an impl or
+/// trait and can name its span after the Self type.
+fn mentions_self(sig: &Signature) -> bool {
+    fn visit(tokens: proc_macro2::TokenStream) -> bool {
+        tokens.into_iter().any(|token| match token {
+            proc_macro2::TokenTree::Ident(ident) => ident == "Self",
+            proc_macro2::TokenTree::Group(group) => visit(group.stream()),
+            _ => false,
+        })
+    }
+
+    sig.receiver().is_some() || visit(quote! { #sig })
+}
+
+fn is_profile_attr(attr: &syn::Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "profile")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_expansion_records_nothing() {
+        let args = quote! { name = "load", category = "io", sample = 10, if = true };
+        let input = quote! {
+            impl Loader {
+                fn open(&self) {}
+                #[profile(sample = 2)]
+                async fn read(&self) -> u32 { 1 }
+            }
+        };
+
+        let enabled = expand(args.clone(), input.clone(), true).to_string();
+        assert!(enabled.contains("racy_client"));
+
+        let disabled = expand(args, input, false).to_string();
+        assert!(!disabled.contains("racy_client"));
+        assert!(!disabled.contains("profile"));
+    }
+
+    #[test]
+    fn methods_are_named_after_self_at_runtime() {
+        let method = expand(quote! {}, quote! { fn new() -> Self { Self } }, true).to_string();
+        assert!(method.contains("ScopedProfiler :: method :: < Self > (& NAME)"));
+        assert!(method.contains("with_location (\"new\""));
+
+        let free = expand(quote! {}, quote! { fn new() -> u32 { 1 } }, true).to_string();
+        assert!(free.contains("ScopedProfiler :: new (& NAME)"));
+    }
+
+    #[test]
+    fn bad_arguments_are_reported() {
+        let error = |args| match syn::parse2::<ProfileArgs>(args) {
+            Ok(_) => panic!("arguments were accepted"),
+            Err(err) => err.to_string(),
+        };
+
+        assert_eq!(
+            error(quote! { foo = 1 }),
+            "unknown #[profile] argument `foo`, expected `name`, `category`, `sample` or `if`"
+        );
+        assert_eq!(
+            error(quote! { name = "a", name = "b" }),
+            "duplicate #[profile] argument `name`"
+        );
+    }
 }
@@ -1,29 +1,63 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use common::write_chrome_trace;
 
 use crate::{
-    data::load_from_file,
-    event::Events,
+    call_tree::CallNode,
+    data::{load_chrome_trace, load_from_file, load_trace},
+    event::{EventSpan, Events},
     widget::{
-        flame_graph::FlameGraphWidget,
-        menu::{open_file_dialog, save_file_dialog, MenuAction, MenuBar},
+        call_tree::CallTreeWidget,
+        flame_graph::{FlameGraphWidget, GroupKey},
+        menu::{
+            MenuAction, MenuBar, ViewMode, export_chrome_trace_dialog, open_file_dialog,
+            save_file_dialog,
+        },
+        span_details::SpanDetailsPanel,
     },
 };
 
 pub struct FlameGraphApp {
     events: Events,
-    folded_processes: HashMap<usize, bool>,
+    folded_processes: HashMap<GroupKey, bool>,
+    view_mode: ViewMode,
+    /// Whether the call tree views merge all threads into one.
+    merge_threads: bool,
+    /// Call trees built for the current events, view mode and merging of
+    /// threads, which are cleared whenever one of those changes.
+    call_trees: HashMap<GroupKey, CallNode>,
+    /// Frame each call tree is zoomed into, by the names on its path.
+    call_tree_zoom: HashMap<GroupKey, Vec<String>>,
+    selected_span: Option<EventSpan>,
     current_file: Option<PathBuf>,
+    /// Whether the current file is a trace rather than a saved view, which
+    /// saving must not overwrite.
+    opened_trace: bool,
     show_error_dialog: Option<String>,
 }
 
 impl Default for FlameGraphApp {
     fn default() -> Self {
+        // Start out empty rather than fail when there is no trace to show.
+        let (events, show_error_dialog) = match load_from_file() {
+            Ok(events) => (events, None),
+            Err(e) => (
+                Events::default(),
+                Some(format!("Failed to load the latest trace: {}", e)),
+            ),
+        };
         Self {
-            events: load_from_file(),
+            events,
             folded_processes: HashMap::new(),
+            view_mode: ViewMode::Timeline,
+            merge_threads: false,
+            call_trees: HashMap::new(),
+            call_tree_zoom: HashMap::new(),
+            selected_span: None,
             current_file: None,
-            show_error_dialog: None,
+            opened_trace: false,
+            show_error_dialog,
         }
     }
 }
@@ -38,20 +72,47 @@ impl FlameGraphApp {
         std::fs::write(&path, json).map_err(|e| format!("Failed to write file: {}", e))?;
 
         self.current_file = Some(path);
+        self.opened_trace = false;
         Ok(())
     }
 
+    fn export_chrome_trace(&self, path: PathBuf) -> Result<(), String> {
+        write_chrome_trace(path, &self.events.to_trace())
+            .map_err(|e| format!("Failed to export trace: {}", e))
+    }
+
     fn load_from_file(&mut self, path: PathBuf) -> Result<(), String> {
-        // Read file
-        let contents =
-            std::fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))?;
+        let (events, opened_trace) = if is_json(&path) {
+            // Read file
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read file: {}", e))?;
 
-        // Deserialize events
-        let events: Events =
-            serde_json::from_str(&contents).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+            // Deserialize events, or failing that take it for a Chrome trace
+            match serde_json::from_str(&contents) {
+                Ok(events) => (events, false),
+                Err(e) => {
+                    let events = load_chrome_trace(&contents).map_err(|chrome_error| {
+                        format!(
+                            "Failed to parse JSON: {}\nAs a Chrome trace: {}",
+                            e, chrome_error
+                        )
+                    })?;
+                    (events, true)
+                }
+            }
+        } else {
+            // Anything else is taken for a racy trace
+            let events =
+                load_trace(path.clone()).map_err(|e| format!("Failed to read trace: {}", e))?;
+            (events, true)
+        };
 
         self.events = events;
+        self.opened_trace = opened_trace;
         self.folded_processes.clear(); // Reset fold states
+        self.call_trees.clear();
+        self.call_tree_zoom.clear();
+        self.selected_span = None;
         self.current_file = Some(path);
         Ok(())
     }
@@ -59,7 +120,11 @@ impl FlameGraphApp {
     fn clear_data(&mut self) {
         self.events.clear();
         self.folded_processes.clear();
+        self.call_trees.clear();
+        self.call_tree_zoom.clear();
+        self.selected_span = None;
         self.current_file = None;
+        self.opened_trace = false;
     }
 
     fn handle_menu_action(&mut self, action: MenuAction, ctx: &egui::Context) {
@@ -68,31 +133,40 @@ impl FlameGraphApp {
                 self.clear_data();
             }
             MenuAction::OpenFile => {
-                if let Some(path) = open_file_dialog() {
-                    if let Err(e) = self.load_from_file(path) {
-                        self.show_error_dialog = Some(e);
-                    }
+                if let Some(path) = open_file_dialog()
+                    && let Err(e) = self.load_from_file(path)
+                {
+                    self.show_error_dialog = Some(e);
                 }
             }
             MenuAction::SaveFile => {
-                if let Some(path) = &self.current_file.clone() {
+                // Saving writes JSON, so never over an opened trace
+                if let Some(path) = &self.current_file.clone().filter(|_| !self.opened_trace) {
                     if let Err(e) = self.save_to_file(path.clone()) {
                         self.show_error_dialog = Some(e);
                     }
                 } else {
                     // If no current file, trigger Save As
-                    if let Some(path) = save_file_dialog() {
-                        if let Err(e) = self.save_to_file(path) {
-                            self.show_error_dialog = Some(e);
-                        }
+                    if let Some(path) = save_file_dialog()
+                        && let Err(e) = self.save_to_file(path)
+                    {
+                        self.show_error_dialog = Some(e);
                     }
                 }
             }
             MenuAction::SaveFileAs => {
-                if let Some(path) = save_file_dialog() {
-                    if let Err(e) = self.save_to_file(path) {
-                        self.show_error_dialog = Some(e);
-                    }
+                if let Some(path) = save_file_dialog()
+                    && let Err(e) = self.save_to_file(path)
+                {
+                    self.show_error_dialog = Some(e);
+                }
+            }
+            MenuAction::ExportChromeTrace => {
+                // Exporting leaves the current file as it is
+                if let Some(path) = export_chrome_trace_dialog()
+                    && let Err(e) = self.export_chrome_trace(path)
+                {
+                    self.show_error_dialog = Some(e);
                 }
             }
             MenuAction::Exit => {
@@ -102,20 +176,32 @@ impl FlameGraphApp {
                 self.folded_processes.clear();
             }
             MenuAction::CollapseAll => {
-                let process_ids: Vec<_> = self
-                    .events
-                    .thread_ids()
-                    .collect();
+                let process_ids: Vec<_> = self.events.process_ids().collect();
 
-                for id in process_ids {
-                    self.folded_processes.insert(*id as usize, true);
+                for pid in process_ids {
+                    self.folded_processes.insert(GroupKey::Process(*pid), true);
+                }
+            }
+            MenuAction::SetViewMode(mode) => {
+                if mode != self.view_mode {
+                    self.call_trees.clear();
                 }
+                self.view_mode = mode;
+            }
+            MenuAction::ToggleMergeThreads => {
+                self.merge_threads = !self.merge_threads;
+                self.call_trees.clear();
             }
             MenuAction::None => {}
         }
     }
 }
 
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
 impl eframe::App for FlameGraphApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Handle error dialog
@@ -132,42 +218,67 @@ impl eframe::App for FlameGraphApp {
         }
 
         // Show menu bar and handle actions
-        let menu_bar = MenuBar::new(&self.current_file);
+        let menu_bar = MenuBar::new(&self.current_file, self.view_mode, self.merge_threads);
         let action = menu_bar.show(ctx);
         self.handle_menu_action(action, ctx);
 
+        // Details of the selected span
+        SpanDetailsPanel::new(&mut self.selected_span).show(ctx);
+
         // Main content
         let mut scroll_output = None;
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Process Event Flamegraphs");
 
-            // Create and show flamegraph widget
-            let flamegraph = FlameGraphWidget::new(&self.events, &mut self.folded_processes);
-            scroll_output = flamegraph.show(ui);
+            match self.view_mode {
+                ViewMode::Timeline => {
+                    // Create and show flamegraph widget
+                    let flamegraph = FlameGraphWidget::new(
+                        &self.events,
+                        &mut self.folded_processes,
+                        &mut self.selected_span,
+                    );
+                    scroll_output = flamegraph.show(ui);
+                }
+                ViewMode::CallTree | ViewMode::BottomUp => {
+                    CallTreeWidget::new(
+                        &self.events,
+                        &mut self.folded_processes,
+                        &mut self.call_trees,
+                        &mut self.call_tree_zoom,
+                        self.merge_threads,
+                        self.view_mode == ViewMode::BottomUp,
+                    )
+                    .show(ui);
+                }
+            }
         });
 
         // Timeline panel (only show if we have events and scroll output)
-        if !self.events.is_empty() {
-            if let Some(output) = scroll_output {
-                egui::TopBottomPanel::bottom("timeline_panel").show(ctx, |ui| {
-                    ui.add_space(5.0);
-
-                    egui::ScrollArea::horizontal()
-                        .id_salt("timeline_scroll")
-                        .scroll_offset(output.state.offset)
-                        .show(ui, |ui| {
-                            let virtual_width = ui.available_width() * 3.0;
-                            ui.set_min_width(virtual_width);
-
-                            let flamegraph =
-                                FlameGraphWidget::new(&self.events, &mut self.folded_processes);
-                            flamegraph.draw_timeline_axis(ui);
-                        });
-
-                    ui.add_space(10.0);
-                });
-            }
+        if !self.events.is_empty()
+            && let Some(output) = scroll_output
+        {
+            egui::TopBottomPanel::bottom("timeline_panel").show(ctx, |ui| {
+                ui.add_space(5.0);
+
+                egui::ScrollArea::horizontal()
+                    .id_salt("timeline_scroll")
+                    .scroll_offset(output.state.offset)
+                    .show(ui, |ui| {
+                        let virtual_width = ui.available_width() * 3.0;
+                        ui.set_min_width(virtual_width);
+
+                        let flamegraph = FlameGraphWidget::new(
+                            &self.events,
+                            &mut self.folded_processes,
+                            &mut self.selected_span,
+                        );
+                        flamegraph.draw_timeline_axis(ui);
+                    });
+
+                ui.add_space(10.0);
+            });
         }
     }
 }
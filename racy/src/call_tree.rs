@@ -0,0 +1,273 @@
+use std::cmp::Ordering;
+
+use crate::event::{EventSpan, Thread};
+
+/// Frame of a call tree, merging every span reached through the same path of
+/// span names.
+#[derive(Debug, Clone, Default)]
+pub struct CallNode {
+    pub name: String,
+    /// Category of the first span merged into the frame, for its colour.
+    pub category: Option<String>,
+    /// Summed duration of the spans merged into the frame.
+    ///
+    /// In a bottom-up tree, the self time of the function at its top that was
+    /// spent under this path of callers.
+    pub total: u64,
+    /// Number of spans merged into the frame.
+    pub count: u64,
+    /// Sorted by name top-down, as flame graphs order them, and by total
+    /// bottom-up.
+    pub children: Vec<CallNode>,
+}
+
+impl CallNode {
+    /// Merges the spans of `threads` by call path, from the outermost span
+    /// down. Async task lifetimes aren't call paths, so pass thread rows only.
+    pub fn top_down<'a>(threads: impl IntoIterator<Item = &'a Thread>) -> Self {
+        let mut root = Self::default();
+        for thread in threads {
+            let parents = Self::parents(thread);
+            for (index, span) in thread.spans.iter().enumerate() {
+                let path = Self::path_to(thread, &parents, index);
+                let node = root.walk(path.iter().rev().copied(), |_| {});
+                node.total += span.duration;
+                node.count += 1;
+            }
+        }
+        root.sort_by(&|a, b| a.name.cmp(&b.name));
+        root.total = root.children.iter().map(|child| child.total).sum();
+        root
+    }
+
+    /// Inverts the call tree of `threads`: the top level holds every span
+    /// name with the self time of its spans, and below each are the callers
+    /// that time was spent under, innermost first.
+    pub fn bottom_up<'a>(threads: impl IntoIterator<Item = &'a Thread>) -> Self {
+        let mut root = Self::default();
+        for thread in threads {
+            let parents = Self::parents(thread);
+            let mut child_time = vec![0; thread.spans.len()];
+            for (index, parent) in parents.iter().enumerate() {
+                if let Some(parent) = *parent {
+                    child_time[parent] += thread.spans[index].duration;
+                }
+            }
+
+            for (index, span) in thread.spans.iter().enumerate() {
+                let self_time = span.duration.saturating_sub(child_time[index]);
+                if self_time == 0 {
+                    continue;
+                }
+                let path = Self::path_to(thread, &parents, index);
+                root.walk(path, |node| {
+                    node.total += self_time;
+                    node.count += 1;
+                });
+                root.total += self_time;
+            }
+        }
+        root.sort_by(&|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        root
+    }
+
+    /// Index of each span's parent on its thread.
+    ///
+    /// Spans are sorted by start, so a span's parent is the innermost one
+    /// before it at the depth above, whether that depth was recorded or
+    /// inferred by `EventsBuilder::update_depth`.
+    fn parents(thread: &Thread) -> Vec<Option<usize>> {
+        let mut parents = Vec::with_capacity(thread.spans.len());
+        let mut path: Vec<usize> = Vec::new();
+        for (index, span) in thread.spans.iter().enumerate() {
+            path.truncate(span.depth as usize);
+            parents.push(path.last().copied());
+            path.push(index);
+        }
+        parents
+    }
+
+    /// The span at `index` followed by its ancestors, outermost last.
+    fn path_to<'t>(
+        thread: &'t Thread,
+        parents: &[Option<usize>],
+        index: usize,
+    ) -> Vec<&'t EventSpan> {
+        let mut path = vec![&thread.spans[index]];
+        let mut ancestor = parents[index];
+        while let Some(parent) = ancestor {
+            path.push(&thread.spans[parent]);
+            ancestor = parents[parent];
+        }
+        path
+    }
+
+    /// Follows `path` down from this frame by span name, creating frames as
+    /// needed and calling `visit` on each one reached, and returns the last.
+    fn walk<'p>(
+        &mut self,
+        path: impl IntoIterator<Item = &'p EventSpan>,
+        mut visit: impl FnMut(&mut CallNode),
+    ) -> &mut CallNode {
+        let mut node = self;
+        for span in path {
+            let index = match node
+                .children
+                .iter()
+                .position(|child| child.name == span.name)
+            {
+                Some(index) => index,
+                None => {
+                    node.children.push(CallNode {
+                        name: span.name.clone(),
+                        category: span.category.clone(),
+                        ..Default::default()
+                    });
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[index];
+            visit(node);
+        }
+        node
+    }
+
+    fn sort_by(&mut self, compare: &impl Fn(&CallNode, &CallNode) -> Ordering) {
+        self.children.sort_by(compare);
+        for child in self.children.iter_mut() {
+            child.sort_by(compare);
+        }
+    }
+
+    /// Time spent in the frame outside of its children.
+    pub fn self_time(&self) -> u64 {
+        let children: u64 = self.children.iter().map(|child| child.total).sum();
+        self.total.saturating_sub(children)
+    }
+
+    /// The frame reached by following child names from this one.
+    pub fn find(&self, path: &[String]) -> Option<&CallNode> {
+        path.iter().try_fold(self, |node, name| {
+            node.children.iter().find(|child| &child.name == name)
+        })
+    }
+
+    /// Deepest level of frames below this one.
+    pub fn height(&self) -> usize {
+        self.children
+            .iter()
+            .map(|child| child.height() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A thread with spans given as `(timestamp, duration, depth, name)`.
+    fn thread(id: u64, spans: &[(u64, u64, u64, &str)]) -> Thread {
+        let mut thread = Thread::new(0, id);
+        thread.spans = spans
+            .iter()
+            .map(|&(timestamp, duration, depth, name)| EventSpan {
+                pid: 0,
+                id,
+                span_id: 0,
+                parent_id: 0,
+                duration,
+                timestamp,
+                depth,
+                name: name.to_string(),
+                location: None,
+                category: None,
+                args: Vec::new(),
+                task_id: 0,
+            })
+            .collect();
+        thread
+    }
+
+    fn path(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn top_down_merges_threads_by_call_path() {
+        let first = thread(
+            1,
+            &[
+                (0, 100, 0, "main"),
+                (10, 30, 1, "work"),
+                (15, 10, 2, "parse"),
+                (50, 20, 1, "work"),
+            ],
+        );
+        let second = thread(2, &[(0, 50, 0, "main"), (0, 20, 1, "work")]);
+
+        let tree = CallNode::top_down([&first, &second]);
+        assert_eq!(tree.total, 150);
+        let main = tree.find(&path(&["main"])).unwrap();
+        assert_eq!((main.total, main.count, main.self_time()), (150, 2, 80));
+        let work = tree.find(&path(&["main", "work"])).unwrap();
+        assert_eq!((work.total, work.count, work.self_time()), (70, 3, 60));
+        let parse = tree.find(&path(&["main", "work", "parse"])).unwrap();
+        assert_eq!((parse.total, parse.self_time()), (10, 10));
+    }
+
+    #[test]
+    fn top_down_keeps_recursion_as_separate_frames() {
+        let recursive = thread(
+            1,
+            &[
+                (0, 100, 0, "walk"),
+                (10, 50, 1, "walk"),
+                (20, 20, 2, "walk"),
+            ],
+        );
+
+        let tree = CallNode::top_down([&recursive]);
+        assert_eq!(tree.height(), 3);
+        let outer = tree.find(&path(&["walk"])).unwrap();
+        assert_eq!((outer.total, outer.self_time()), (100, 50));
+        let inner = tree.find(&path(&["walk", "walk", "walk"])).unwrap();
+        assert_eq!((inner.total, inner.count), (20, 1));
+    }
+
+    #[test]
+    fn bottom_up_splits_self_time_between_callers() {
+        let thread = thread(
+            1,
+            &[
+                (0, 100, 0, "render"),
+                (10, 30, 1, "alloc"),
+                (100, 100, 0, "load"),
+                (110, 10, 1, "alloc"),
+            ],
+        );
+
+        let tree = CallNode::bottom_up([&thread]);
+        assert_eq!(tree.total, 200);
+        let names: Vec<&str> = tree
+            .children
+            .iter()
+            .map(|node| node.name.as_str())
+            .collect();
+        assert_eq!(names, ["load", "render", "alloc"]);
+
+        let alloc = tree.find(&path(&["alloc"])).unwrap();
+        assert_eq!((alloc.total, alloc.count), (40, 2));
+        let shares: Vec<(&str, f64)> = alloc
+            .children
+            .iter()
+            .map(|caller| {
+                let share = caller.total as f64 / alloc.total as f64;
+                (caller.name.as_str(), share)
+            })
+            .collect();
+        assert_eq!(shares, [("render", 0.75), ("load", 0.25)]);
+        // Spans only count their own time, not their callees'.
+        assert_eq!(tree.find(&path(&["render"])).unwrap().total, 70);
+    }
+}
@@ -1,15 +1,36 @@
-use std::{collections::HashMap, u128};
+use std::{collections::HashMap, error::Error};
 
-use common::Event;
+use common::{
+    Arg, ArgValue, ClockSource, Counter, Event, Flow, FlowPhase, Header, InstantScope, Location,
+    Mark, ProcessInfo, ThreadInfo, Trace,
+};
 use serde::{Deserialize, Serialize};
 
 pub struct EventsBuilder {
     events: Vec<Event>,
+    marks: Vec<Mark>,
+    counters: Vec<Counter>,
+    flows: Vec<Flow>,
+    threads: Vec<ThreadInfo>,
+    processes: Vec<ProcessInfo>,
+}
+
+impl Default for EventsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EventsBuilder {
     pub fn new() -> Self {
-        Self { events: Vec::new() }
+        Self {
+            events: Vec::new(),
+            marks: Vec::new(),
+            counters: Vec::new(),
+            flows: Vec::new(),
+            threads: Vec::new(),
+            processes: Vec::new(),
+        }
     }
 
     pub fn add(&mut self, event: Event) {
@@ -20,12 +41,94 @@ impl EventsBuilder {
         self.events.append(&mut events);
     }
 
-    fn partition(events: Vec<EventSpan>) -> HashMap<u64, Thread> {
-        let mut result: HashMap<u64, Thread> = HashMap::new();
+    pub fn add_marks(&mut self, mut marks: Vec<Mark>) {
+        self.marks.append(&mut marks);
+    }
+
+    pub fn add_counters(&mut self, mut counters: Vec<Counter>) {
+        self.counters.append(&mut counters);
+    }
+
+    pub fn add_flows(&mut self, mut flows: Vec<Flow>) {
+        self.flows.append(&mut flows);
+    }
+
+    pub fn add_threads(&mut self, mut threads: Vec<ThreadInfo>) {
+        self.threads.append(&mut threads);
+    }
+
+    pub fn add_processes(&mut self, mut processes: Vec<ProcessInfo>) {
+        self.processes.append(&mut processes);
+    }
+
+    /// Pairs every flow end with the begin of the same id, dropping ends
+    /// whose begin wasn't recorded and those outside of any span.
+    fn link_flows(flows: Vec<Flow>, min_timestamp: u128) -> Vec<EventFlow> {
+        let point = |flow: &Flow| FlowPoint {
+            pid: flow.pid,
+            thread: flow.thread,
+            span_id: flow.span_id,
+            timestamp: flow.timestamp.saturating_sub(min_timestamp) as u64,
+        };
+        let begins: HashMap<u64, FlowPoint> = flows
+            .iter()
+            .filter(|flow| flow.phase == FlowPhase::Begin && flow.span_id != 0)
+            .map(|flow| (flow.id, point(flow)))
+            .collect();
+
+        flows
+            .iter()
+            .filter(|flow| flow.phase == FlowPhase::End && flow.span_id != 0)
+            .filter_map(|flow| {
+                Some(EventFlow {
+                    id: flow.id,
+                    begin: begins.get(&flow.id)?.clone(),
+                    end: point(flow),
+                })
+            })
+            .collect()
+    }
+
+    /// Groups counter samples into one time-ordered track per name.
+    fn counter_tracks(counters: Vec<Counter>, min_timestamp: u128) -> Vec<CounterTrack> {
+        let mut tracks: Vec<CounterTrack> = Vec::new();
+        for counter in counters {
+            let sample = CounterSample {
+                timestamp: (counter.timestamp - min_timestamp) as u64,
+                value: counter.value,
+            };
+            match tracks
+                .iter_mut()
+                .find(|track| track.pid == counter.pid && track.name == counter.name)
+            {
+                Some(track) => track.samples.push(sample),
+                None => tracks.push(CounterTrack {
+                    pid: counter.pid,
+                    name: counter.name,
+                    samples: vec![sample],
+                }),
+            }
+        }
+        for track in tracks.iter_mut() {
+            track.samples.sort_by_key(|sample| sample.timestamp);
+        }
+        tracks.sort_by(|a, b| a.name.cmp(&b.name).then(a.pid.cmp(&b.pid)));
+        tracks
+    }
+
+    fn partition(events: Vec<EventSpan>) -> HashMap<u32, Process> {
+        let mut result: HashMap<u32, Process> = HashMap::new();
 
         for event in events {
-            let id = event.id;
-            result.entry(id).or_insert(Thread::new(id)).spans.push(event);
+            let (pid, id) = (event.pid, event.id);
+            result
+                .entry(pid)
+                .or_insert_with(|| Process::new(pid))
+                .threads
+                .entry(id)
+                .or_insert_with(|| Thread::new(pid, id))
+                .spans
+                .push(event);
         }
 
         result
@@ -35,15 +138,24 @@ impl EventsBuilder {
         raw_events
             .into_iter()
             .map(|event| EventSpan {
+                pid: event.pid,
                 id: event.id,
+                span_id: event.span_id,
+                parent_id: event.parent_id,
                 duration: event.duration,
                 timestamp: (event.timestamp - min_timestamp) as u64,
-                depth: 0,
+                depth: event.depth as u64,
                 name: event.name,
+                location: event.location.map(SourceLocation::from),
+                category: event.category,
+                args: event.args.into_iter().map(SpanArg::from).collect(),
+                task_id: event.task_id,
             })
             .collect()
     }
 
+    /// Infers nesting from timestamp overlap, for spans recorded without
+    /// parent linkage.
     fn update_depth(spans: &mut Vec<EventSpan>) {
         spans.sort();
         let mut stack: Vec<u64> = Vec::new();
@@ -56,46 +168,193 @@ impl EventsBuilder {
         }
     }
 
-    pub fn build(self) -> Events {
-        let min_timestamp = self
+    /// Puts each span on the first lane that is free when it starts, for spans
+    /// that overlap without nesting.
+    fn pack_lanes(spans: &mut Vec<EventSpan>) {
+        spans.sort();
+        let mut lane_ends: Vec<u64> = Vec::new();
+        for span in spans {
+            let lane = match lane_ends.iter().position(|&end| end <= span.timestamp) {
+                Some(lane) => lane,
+                None => {
+                    lane_ends.push(0);
+                    lane_ends.len() - 1
+                }
+            };
+            lane_ends[lane] = span.timestamp + span.duration;
+            span.depth = lane as u64;
+        }
+    }
+
+    /// Lays the records out for the viewer, failing if there is nothing to
+    /// show, such as for a process that exited before recording anything.
+    pub fn build(self) -> Result<Events, Box<dyn Error>> {
+        let Some(min_timestamp) = self
             .events
             .iter()
             .map(|e| e.timestamp)
-            .min().unwrap();
+            .chain(self.marks.iter().map(|mark| mark.timestamp))
+            .chain(self.counters.iter().map(|counter| counter.timestamp))
+            .min()
+        else {
+            return Err("The trace holds no spans, marks or counters".into());
+        };
 
         let spans = Self::convert(self.events, min_timestamp);
+        let marks: Vec<EventMark> = self
+            .marks
+            .into_iter()
+            .map(|mark| EventMark::new(mark, min_timestamp))
+            .collect();
+        let counters = Self::counter_tracks(self.counters, min_timestamp);
+        let flows = Self::link_flows(self.flows, min_timestamp);
+
+        let total_duration = spans
+            .iter()
+            .map(|event| event.timestamp + event.duration)
+            .chain(marks.iter().map(|mark| mark.timestamp))
+            .chain(
+                counters
+                    .iter()
+                    .flat_map(|track| track.samples.last())
+                    .map(|sample| sample.timestamp),
+            )
+            .max()
+            .unwrap_or(0);
+
+        // Task spans can outlive the polls beneath them and hop threads, so
+        // they get a lane group of their own in their process.
+        let (task_spans, spans): (Vec<_>, Vec<_>) = spans.into_iter().partition(EventSpan::is_task);
+        let mut processes = Self::partition(spans);
+        for span in task_spans {
+            let pid = span.pid;
+            processes
+                .entry(pid)
+                .or_insert_with(|| Process::new(pid))
+                .tasks
+                .spans
+                .push(span);
+        }
 
-        let total_duration = spans.iter().map(|event| event.timestamp + event.duration).max().unwrap();
+        // A thread that only recorded marks still needs a row to show them on.
+        for mark in marks.iter().filter(|mark| mark.scope == MarkScope::Thread) {
+            processes
+                .entry(mark.pid)
+                .or_insert_with(|| Process::new(mark.pid))
+                .threads
+                .entry(mark.id)
+                .or_insert_with(|| Thread::new(mark.pid, mark.id));
+        }
 
+        for info in self.threads {
+            if let Some(thread) = processes
+                .get_mut(&info.pid)
+                .and_then(|process| process.threads.get_mut(&info.id))
+            {
+                thread.name = Some(info.name);
+            }
+        }
 
-        let mut partitioned = Self::partition(spans);
+        for info in self.processes {
+            if let Some(process) = processes.get_mut(&info.pid) {
+                process.name = Some(info.exe_name).filter(|name| !name.is_empty());
+                process.command_line = info.command_line;
+            }
+        }
 
-        partitioned.values_mut().for_each( |thread| {
-            Self::update_depth(&mut thread.spans);
-        });
+        for process in processes.values_mut() {
+            Self::pack_lanes(&mut process.tasks.spans);
+            process.threads.values_mut().for_each(|thread| {
+                if thread.spans.iter().all(|span| span.span_id != 0) {
+                    // Depth was captured along with the parent id; keep it.
+                    thread.spans.sort();
+                } else {
+                    Self::update_depth(&mut thread.spans);
+                }
+            });
+        }
 
-        Events {
+        Ok(Events {
             start_time: min_timestamp,
-            threads: partitioned,
-            total_duration,   
+            processes,
+            marks,
+            counters,
+            flows,
+            total_duration,
+        })
+    }
+}
+
+/// A recorded process and the threads it ran.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Process {
+    pub pid: u32,
+    /// Executable name the client recorded, if any.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub command_line: Vec<String>,
+    pub threads: HashMap<u64, Thread>,
+    /// Lifetime spans of async tasks, packed into lanes.
+    #[serde(default)]
+    pub tasks: Thread,
+}
+
+impl Process {
+    pub fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            tasks: Thread::new(pid, 0),
+            ..Default::default()
+        }
+    }
+
+    /// The executable name and pid, or just the pid for files without names.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({})", name, self.pid),
+            None => format!("Process {}", self.pid),
         }
     }
+
+    pub fn span_count(&self) -> usize {
+        self.threads
+            .values()
+            .map(|thread| thread.spans.len())
+            .sum::<usize>()
+            + self.tasks.spans.len()
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Thread {
+    #[serde(default)]
+    pub pid: u32,
     pub id: u64,
+    /// Name the client recorded for the thread, if any.
+    #[serde(default)]
+    pub name: Option<String>,
     pub spans: Vec<EventSpan>,
 }
 
 impl Thread {
-    pub fn new(id: u64) -> Self {
+    pub fn new(pid: u32, id: u64) -> Self {
         Self {
+            pid,
             id,
+            name: None,
             spans: Vec::new(),
         }
     }
 
+    /// The thread's name, or its id for files recorded without names.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("Thread {}", self.id),
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.spans.is_empty()
     }
@@ -103,61 +362,508 @@ impl Thread {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct EventSpan {
+    #[serde(default)]
+    pub pid: u32,
     pub id: u64,
+    #[serde(default)]
+    pub span_id: u64,
+    #[serde(default)]
+    pub parent_id: u64,
     pub duration: u64,
     pub timestamp: u64,
     pub depth: u64,
     pub name: String,
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub args: Vec<SpanArg>,
+    /// Span id of the async task this span ran in, 0 outside of tasks.
+    #[serde(default)]
+    pub task_id: u64,
 }
 
-impl PartialOrd for EventSpan {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.timestamp.partial_cmp(&other.timestamp) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
+impl EventSpan {
+    /// Whether this span covers the whole lifetime of an async task.
+    pub fn is_task(&self) -> bool {
+        self.task_id != 0 && self.task_id == self.span_id
+    }
+}
+
+/// Arrow from the span a flow began in to a span it ended in.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EventFlow {
+    pub id: u64,
+    pub begin: FlowPoint,
+    pub end: FlowPoint,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FlowPoint {
+    pub pid: u32,
+    pub thread: u64,
+    pub span_id: u64,
+    /// Relative to the start of the trace, like span timestamps.
+    pub timestamp: u64,
+}
+
+/// Samples of one counter, ordered by time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CounterTrack {
+    #[serde(default)]
+    pub pid: u32,
+    pub name: String,
+    pub samples: Vec<CounterSample>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CounterSample {
+    pub timestamp: u64,
+    pub value: f64,
+}
+
+impl CounterTrack {
+    /// Lowest and highest value, with the lowest no higher than zero.
+    pub fn range(&self) -> (f64, f64) {
+        self.samples
+            .iter()
+            .fold((0.0, f64::MIN), |(low, high), sample| {
+                (low.min(sample.value), high.max(sample.value))
+            })
+    }
+
+    /// The sample in effect at `timestamp`, if any was taken by then.
+    pub fn value_at(&self, timestamp: u64) -> Option<&CounterSample> {
+        let index = self
+            .samples
+            .partition_point(|sample| sample.timestamp <= timestamp);
+        index.checked_sub(1).map(|index| &self.samples[index])
+    }
+}
+
+/// Instant event, with its timestamp relative to the start of the trace.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EventMark {
+    #[serde(default)]
+    pub pid: u32,
+    pub id: u64,
+    pub timestamp: u64,
+    pub name: String,
+    pub scope: MarkScope,
+    #[serde(default)]
+    pub args: Vec<SpanArg>,
+}
+
+impl EventMark {
+    fn new(mark: Mark, min_timestamp: u128) -> Self {
+        Self {
+            pid: mark.pid,
+            id: mark.id,
+            timestamp: (mark.timestamp - min_timestamp) as u64,
+            name: mark.name,
+            scope: mark.scope.into(),
+            args: mark.args.into_iter().map(SpanArg::from).collect(),
         }
+    }
 
-        match self.duration.partial_cmp(&other.duration) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord.map(|ord| ord.reverse()),
+    /// Whether the mark belongs on the row of `thread_id` in process `pid`.
+    pub fn applies_to(&self, pid: u32, thread_id: u64) -> bool {
+        match self.scope {
+            MarkScope::Thread => self.pid == pid && self.id == thread_id,
+            MarkScope::Process => self.pid == pid,
+            MarkScope::Global => true,
         }
+    }
+}
 
-        match self.id.partial_cmp(&other.id) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MarkScope {
+    Thread,
+    Process,
+    Global,
+}
+
+impl From<InstantScope> for MarkScope {
+    fn from(scope: InstantScope) -> Self {
+        match scope {
+            InstantScope::Thread => MarkScope::Thread,
+            InstantScope::Process => MarkScope::Process,
+            InstantScope::Global => MarkScope::Global,
+        }
+    }
+}
+
+impl From<MarkScope> for InstantScope {
+    fn from(scope: MarkScope) -> Self {
+        match scope {
+            MarkScope::Thread => InstantScope::Thread,
+            MarkScope::Process => InstantScope::Process,
+            MarkScope::Global => InstantScope::Global,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLocation {
+    pub module_path: String,
+    pub file: String,
+    pub line: u32,
+}
+
+impl From<Location> for SourceLocation {
+    fn from(location: Location) -> Self {
+        Self {
+            module_path: location.module_path,
+            file: location.file,
+            line: location.line,
         }
-        
-        match self.depth.partial_cmp(&other.depth) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
+    }
+}
+
+impl From<SourceLocation> for Location {
+    fn from(location: SourceLocation) -> Self {
+        Self {
+            module_path: location.module_path,
+            file: location.file,
+            line: location.line,
         }
-        self.name.partial_cmp(&other.name)
+    }
+}
+
+impl PartialOrd for EventSpan {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for EventSpan {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        self.timestamp
+            .cmp(&other.timestamp)
+            .then_with(|| self.duration.cmp(&other.duration).reverse())
+            .then_with(|| self.pid.cmp(&other.pid))
+            .then_with(|| self.id.cmp(&other.id))
+            .then_with(|| self.depth.cmp(&other.depth))
+            .then_with(|| self.name.cmp(&other.name))
+            .then_with(|| self.location.cmp(&other.location))
+            .then_with(|| self.category.cmp(&other.category))
+            .then_with(|| self.args.cmp(&other.args))
+            .then_with(|| self.task_id.cmp(&other.task_id))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpanArg {
+    pub key: String,
+    pub value: SpanArgValue,
+}
+
+impl From<Arg> for SpanArg {
+    fn from(arg: Arg) -> Self {
+        let value = match arg.value {
+            ArgValue::Int(value) => SpanArgValue::Int(value),
+            ArgValue::UInt(value) => SpanArgValue::UInt(value),
+            ArgValue::Float(value) => SpanArgValue::Float(value),
+            ArgValue::Str(value) => SpanArgValue::Str(value.into_owned()),
+            ArgValue::Bool(value) => SpanArgValue::Bool(value),
+        };
+        Self {
+            key: arg.key.into_owned(),
+            value,
+        }
+    }
+}
+
+impl From<SpanArg> for Arg {
+    fn from(arg: SpanArg) -> Self {
+        let value = match arg.value {
+            SpanArgValue::Int(value) => ArgValue::Int(value),
+            SpanArgValue::UInt(value) => ArgValue::UInt(value),
+            SpanArgValue::Float(value) => ArgValue::Float(value),
+            SpanArgValue::Str(value) => ArgValue::Str(value.into()),
+            SpanArgValue::Bool(value) => ArgValue::Bool(value),
+        };
+        Arg::new(arg.key, value)
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SpanArgValue {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+}
+
+impl SpanArgValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SpanArgValue::Int(_) => "int",
+            SpanArgValue::UInt(_) => "uint",
+            SpanArgValue::Float(_) => "float",
+            SpanArgValue::Str(_) => "string",
+            SpanArgValue::Bool(_) => "bool",
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            SpanArgValue::Int(_) => 0,
+            SpanArgValue::UInt(_) => 1,
+            SpanArgValue::Float(_) => 2,
+            SpanArgValue::Str(_) => 3,
+            SpanArgValue::Bool(_) => 4,
+        }
+    }
+}
+
+impl std::fmt::Display for SpanArgValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpanArgValue::Int(value) => write!(f, "{}", value),
+            SpanArgValue::UInt(value) => write!(f, "{}", value),
+            SpanArgValue::Float(value) => write!(f, "{}", value),
+            SpanArgValue::Str(value) => write!(f, "{}", value),
+            SpanArgValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl PartialEq for SpanArgValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for SpanArgValue {}
+
+impl PartialOrd for SpanArgValue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SpanArgValue {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (SpanArgValue::Int(a), SpanArgValue::Int(b)) => a.cmp(b),
+            (SpanArgValue::UInt(a), SpanArgValue::UInt(b)) => a.cmp(b),
+            (SpanArgValue::Float(a), SpanArgValue::Float(b)) => a.total_cmp(b),
+            (SpanArgValue::Str(a), SpanArgValue::Str(b)) => a.cmp(b),
+            (SpanArgValue::Bool(a), SpanArgValue::Bool(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "SavedEvents")]
 pub struct Events {
     pub start_time: u128,
     pub total_duration: u64,
-    pub threads: HashMap<u64, Thread>,
+    pub processes: HashMap<u32, Process>,
+    #[serde(default)]
+    pub marks: Vec<EventMark>,
+    #[serde(default)]
+    pub counters: Vec<CounterTrack>,
+    #[serde(default)]
+    pub flows: Vec<EventFlow>,
+}
+
+/// [`Events`] as saved by any version of the viewer, including those from
+/// before processes, which kept every thread in one top-level map.
+#[derive(Deserialize)]
+struct SavedEvents {
+    start_time: u128,
+    total_duration: u64,
+    #[serde(default)]
+    processes: HashMap<u32, Process>,
+    #[serde(default)]
+    threads: HashMap<u64, Thread>,
+    #[serde(default)]
+    marks: Vec<EventMark>,
+    #[serde(default)]
+    counters: Vec<CounterTrack>,
+    #[serde(default)]
+    flows: Vec<EventFlow>,
+}
+
+impl From<SavedEvents> for Events {
+    fn from(saved: SavedEvents) -> Self {
+        let mut processes = saved.processes;
+        // The legacy threads didn't record a pid, so they all went to pid 0.
+        if !saved.threads.is_empty() {
+            processes
+                .entry(0)
+                .or_insert_with(|| Process::new(0))
+                .threads = saved.threads;
+        }
+        Self {
+            start_time: saved.start_time,
+            total_duration: saved.total_duration,
+            processes,
+            marks: saved.marks,
+            counters: saved.counters,
+            flows: saved.flows,
+        }
+    }
 }
 
 impl Events {
     pub fn clear(&mut self) {
-        self.threads.clear();
+        self.processes.clear();
+        self.marks.clear();
+        self.counters.clear();
+        self.flows.clear();
     }
 
     pub fn is_empty(&self) -> bool {
-        self.threads.is_empty()
+        self.processes.is_empty() && self.counters.is_empty()
+    }
+
+    pub fn process_ids(&self) -> impl Iterator<Item = &u32> {
+        self.processes.keys()
+    }
+
+    /// Turns the events back into records with wall-clock timestamps, e.g.
+    /// to export them in another format.
+    pub fn to_trace(&self) -> Trace {
+        let timestamp = |offset: u64| self.start_time + offset as u128;
+        let mut header = Header::current(ClockSource::Realtime);
+        header.start_time = self.start_time;
+
+        let mut processes: Vec<&Process> = self.processes.values().collect();
+        processes.sort_by_key(|process| process.pid);
+        let threads = || {
+            processes.iter().flat_map(|process| {
+                let mut threads: Vec<&Thread> = process.threads.values().collect();
+                threads.sort_by_key(|thread| thread.id);
+                threads.into_iter().chain([&process.tasks])
+            })
+        };
+
+        let events = threads()
+            .flat_map(|thread| &thread.spans)
+            .map(|span| Event {
+                pid: span.pid,
+                id: span.id,
+                span_id: span.span_id,
+                parent_id: span.parent_id,
+                depth: span.depth as u32,
+                duration: span.duration,
+                timestamp: timestamp(span.timestamp),
+                name: span.name.clone(),
+                location: span.location.clone().map(Location::from),
+                category: span.category.clone(),
+                args: span.args.iter().cloned().map(Arg::from).collect(),
+                task_id: span.task_id,
+            })
+            .collect();
+
+        let marks = self
+            .marks
+            .iter()
+            .map(|mark| Mark {
+                pid: mark.pid,
+                id: mark.id,
+                timestamp: timestamp(mark.timestamp),
+                name: mark.name.clone(),
+                scope: mark.scope.into(),
+                args: mark.args.iter().cloned().map(Arg::from).collect(),
+            })
+            .collect();
+
+        // Tracks don't keep the thread a sample was taken on.
+        let counters = self
+            .counters
+            .iter()
+            .flat_map(|track| {
+                track.samples.iter().map(|sample| Counter {
+                    pid: track.pid,
+                    id: 0,
+                    timestamp: timestamp(sample.timestamp),
+                    name: track.name.clone(),
+                    value: sample.value,
+                })
+            })
+            .collect();
+
+        // Several ends can share a begin; write it once.
+        let mut flows: Vec<Flow> = Vec::new();
+        for flow in &self.flows {
+            let point = |phase, point: &FlowPoint| Flow {
+                id: flow.id,
+                phase,
+                pid: point.pid,
+                thread: point.thread,
+                span_id: point.span_id,
+                timestamp: timestamp(point.timestamp),
+            };
+            let begin = point(FlowPhase::Begin, &flow.begin);
+            if !flows.contains(&begin) {
+                flows.push(begin);
+            }
+            flows.push(point(FlowPhase::End, &flow.end));
+        }
+
+        let threads = threads()
+            .filter(|thread| thread.id != 0)
+            .filter_map(|thread| {
+                Some(ThreadInfo {
+                    pid: thread.pid,
+                    id: thread.id,
+                    name: thread.name.clone()?,
+                })
+            })
+            .collect();
+
+        let processes = processes
+            .iter()
+            .map(|process| ProcessInfo {
+                pid: process.pid,
+                exe_name: process.name.clone().unwrap_or_default(),
+                command_line: process.command_line.clone(),
+            })
+            .collect();
+
+        Trace {
+            header,
+            events,
+            marks,
+            counters,
+            flows,
+            threads,
+            processes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_saved_before_processes_load() {
+        let saved = r#"{
+            "start_time": 1000,
+            "total_duration": 10,
+            "threads": {
+                "7": {"id": 7, "spans": [
+                    {"id": 7, "duration": 10, "timestamp": 0, "depth": 0, "name": "outer"}
+                ]}
+            }
+        }"#;
+
+        let events: Events = serde_json::from_str(saved).unwrap();
+        let thread = &events.processes[&0].threads[&7];
+        assert_eq!(thread.spans[0].name, "outer");
+        assert!(events.marks.is_empty());
     }
 
-    pub fn thread_ids(&self) -> impl Iterator<Item = &u64> {
-        self.threads.keys()
+    #[test]
+    fn traces_without_records_are_an_error() {
+        assert!(EventsBuilder::new().build().is_err());
     }
 }
@@ -3,6 +3,7 @@ use eframe::egui;
 use crate::app::FlameGraphApp;
 
 pub mod app;
+pub mod call_tree;
 pub mod data;
 pub mod event;
 pub mod widget;
@@ -0,0 +1,381 @@
+use egui;
+use std::collections::HashMap;
+
+use crate::{
+    call_tree::CallNode,
+    event::{Events, Process, Thread},
+    widget::flame_graph::{FlameGraphWidget, GroupKey, format_duration, frame_label},
+};
+
+/// Flame graph merging identical call paths, where a frame's width is the
+/// share of time spent in it rather than when it ran, or, bottom-up, a list of
+/// span names by self time that expand into their callers.
+pub struct CallTreeWidget<'a> {
+    events: &'a Events,
+    folded_processes: &'a mut HashMap<GroupKey, bool>,
+    /// Trees already built, kept by the caller until the events or the way
+    /// they are merged change.
+    trees: &'a mut HashMap<GroupKey, CallNode>,
+    /// Path of frame names each tree is zoomed into, empty for the whole tree.
+    zoom: &'a mut HashMap<GroupKey, Vec<String>>,
+    merge_threads: bool,
+    bottom_up: bool,
+}
+
+/// A frame drawn this frame, for hovering and clicking.
+struct FrameRect<'n> {
+    rect: egui::Rect,
+    row: usize,
+    path: Vec<String>,
+    node: &'n CallNode,
+}
+
+impl<'a> CallTreeWidget<'a> {
+    pub fn new(
+        events: &'a Events,
+        folded_processes: &'a mut HashMap<GroupKey, bool>,
+        trees: &'a mut HashMap<GroupKey, CallNode>,
+        zoom: &'a mut HashMap<GroupKey, Vec<String>>,
+        merge_threads: bool,
+        bottom_up: bool,
+    ) -> Self {
+        Self {
+            events,
+            folded_processes,
+            trees,
+            zoom,
+            merge_threads,
+            bottom_up,
+        }
+    }
+
+    pub fn show(mut self, ui: &mut egui::Ui) {
+        if self.events.is_empty() {
+            ui.centered_and_justified(|ui| {
+                ui.label("No events loaded. Use File → Open to load a profile.");
+            });
+            return;
+        }
+
+        let events = self.events;
+        let mut processes: Vec<&Process> = events.processes.values().collect();
+        processes.sort_by_key(|process| process.pid);
+
+        egui::ScrollArea::vertical()
+            .id_salt("call_tree_scroll")
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                if self.merge_threads {
+                    let threads: Vec<&Thread> = processes
+                        .iter()
+                        .flat_map(|process| sorted_threads(process))
+                        .collect();
+                    let key = GroupKey::AllThreads;
+                    let title = "All threads".to_string();
+                    let summary = format!("({} threads)", threads.len());
+                    self.draw_group(ui, key, title, summary, |this, ui| {
+                        this.draw(ui, key, &threads);
+                    });
+                } else {
+                    for process in processes {
+                        let key = GroupKey::Process(process.pid);
+                        let summary = format!("({} threads)", process.threads.len());
+                        self.draw_group(ui, key, process.label(), summary, |this, ui| {
+                            for thread in sorted_threads(process) {
+                                let key = GroupKey::Thread(process.pid, thread.id);
+                                let summary = format!("({} spans)", thread.spans.len());
+                                this.draw_group(ui, key, thread.label(), summary, |this, ui| {
+                                    this.draw(ui, key, &[thread]);
+                                });
+                            }
+                        });
+                    }
+                }
+                ui.add_space(60.0);
+            });
+    }
+
+    fn draw_group(
+        &mut self,
+        ui: &mut egui::Ui,
+        fold_key: GroupKey,
+        title: String,
+        summary: String,
+        contents: impl FnOnce(&mut Self, &mut egui::Ui),
+    ) {
+        let is_folded = self
+            .folded_processes
+            .get(&fold_key)
+            .copied()
+            .unwrap_or(false);
+
+        egui::Frame::new()
+            .inner_margin(egui::Margin::same(8))
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+
+                ui.horizontal(|ui| {
+                    let button_text = if is_folded { "▶" } else { "▼" };
+                    if ui.small_button(button_text).clicked() {
+                        self.folded_processes.insert(fold_key, !is_folded);
+                    }
+
+                    ui.label(egui::RichText::new(title).size(14.0).strong());
+                    ui.label(summary);
+                });
+
+                if !is_folded {
+                    ui.add_space(5.0);
+                    contents(self, ui);
+                }
+            });
+
+        ui.add_space(5.0);
+    }
+
+    /// Draws the tree of `threads`, building it only the first time.
+    fn draw(&mut self, ui: &mut egui::Ui, key: GroupKey, threads: &[&Thread]) {
+        let bottom_up = self.bottom_up;
+        let tree = self.trees.entry(key).or_insert_with(|| {
+            if bottom_up {
+                CallNode::bottom_up(threads.iter().copied())
+            } else {
+                CallNode::top_down(threads.iter().copied())
+            }
+        });
+
+        if tree.total == 0 {
+            ui.label("No complete event spans to display");
+        } else if bottom_up {
+            Self::draw_callers(ui, key, tree);
+        } else {
+            Self::draw_tree(ui, self.zoom, key, tree);
+        }
+    }
+
+    /// Lists span names by self time, each expanding into the callers it was
+    /// spent under, with shares of the level above.
+    fn draw_callers(ui: &mut egui::Ui, key: GroupKey, tree: &CallNode) {
+        ui.label(
+            egui::RichText::new(format!(
+                "{:>10} {:>7} {:>7}  {}",
+                "Self", "Share", "Calls", "Name"
+            ))
+            .monospace()
+            .strong(),
+        );
+        let mut path = Vec::new();
+        for node in &tree.children {
+            Self::draw_caller(ui, key, node, tree.total, &mut path);
+        }
+    }
+
+    fn draw_caller(
+        ui: &mut egui::Ui,
+        key: GroupKey,
+        node: &CallNode,
+        parent_total: u64,
+        path: &mut Vec<String>,
+    ) {
+        let share = node.total as f64 / parent_total.max(1) as f64 * 100.0;
+        let text = egui::RichText::new(format!(
+            "{:>10} {:>6.1}% {:>7}  {}",
+            format_duration(node.total),
+            share,
+            node.count,
+            node.name
+        ))
+        .monospace();
+
+        path.push(node.name.clone());
+        if node.children.is_empty() {
+            // Line up with the entries that have an expand arrow.
+            ui.horizontal(|ui| {
+                ui.add_space(ui.spacing().icon_width + ui.spacing().icon_spacing);
+                ui.label(text);
+            });
+        } else {
+            egui::CollapsingHeader::new(text)
+                .id_salt((key, path.clone()))
+                .show(ui, |ui| {
+                    for caller in &node.children {
+                        Self::draw_caller(ui, key, caller, node.total, path);
+                    }
+                });
+        }
+        path.pop();
+    }
+
+    /// Draws the frame the tree is zoomed into across the full width, with
+    /// its ancestors above it and its descendants below.
+    fn draw_tree(
+        ui: &mut egui::Ui,
+        zooms: &mut HashMap<GroupKey, Vec<String>>,
+        key: GroupKey,
+        tree: &CallNode,
+    ) {
+        // A zoomed frame is gone once threads are merged or split differently.
+        let mut zoom = zooms.get(&key).cloned().unwrap_or_default();
+        let focus = match tree.find(&zoom) {
+            Some(focus) => focus,
+            None => {
+                zoom.clear();
+                tree
+            }
+        };
+
+        let block_height = 25.0;
+        let block_spacing = 2.0;
+        let rows = zoom.len() + 1 + focus.height();
+        let graph_height = rows as f32 * (block_height + block_spacing) + 20.0;
+        let (response, painter) = ui.allocate_painter(
+            egui::Vec2::new(ui.available_width(), graph_height),
+            egui::Sense::click(),
+        );
+        let rect = response.rect;
+        let row_rect = |row: usize, left: f32, width: f32| {
+            egui::Rect::from_min_size(
+                egui::pos2(
+                    left,
+                    rect.top() + row as f32 * (block_height + block_spacing),
+                ),
+                egui::Vec2::new(width, block_height),
+            )
+        };
+
+        let mut frames = Vec::new();
+        let mut node = tree;
+        for depth in 0..=zoom.len() {
+            frames.push(FrameRect {
+                rect: row_rect(depth, rect.left(), rect.width()),
+                row: depth,
+                path: zoom[..depth].to_vec(),
+                node,
+            });
+            if let Some(name) = zoom.get(depth) {
+                node = node.find(std::slice::from_ref(name)).unwrap();
+            }
+        }
+        let scale = rect.width() / focus.total.max(1) as f32;
+        let mut path = zoom.clone();
+        let mut left = rect.left();
+        for child in &focus.children {
+            Self::layout(
+                child,
+                &mut path,
+                left,
+                zoom.len() + 1,
+                scale,
+                &row_rect,
+                &mut frames,
+            );
+            left += child.total as f32 * scale;
+        }
+
+        let hover_pos = response.hover_pos();
+        let mut hovered = None;
+        for frame in &frames {
+            let name = if frame.path.is_empty() {
+                "all"
+            } else {
+                &frame.node.name
+            };
+            let mut color = FlameGraphWidget::block_color(
+                frame.node.category.as_deref(),
+                name,
+                frame.row as u64,
+            );
+            // Dim the frames above the zoomed one; clicking them zooms out.
+            if frame.row < zoom.len() {
+                color = color.gamma_multiply(0.5);
+            }
+
+            if hover_pos.is_some_and(|pos| frame.rect.contains(pos)) {
+                hovered = Some(frame);
+            }
+
+            painter.rect_filled(frame.rect, egui::CornerRadius::same(2), color);
+            painter.rect_stroke(
+                frame.rect,
+                egui::CornerRadius::same(2),
+                egui::Stroke::new(1.0_f32, egui::Color32::from_gray(60)),
+                egui::StrokeKind::Inside,
+            );
+
+            let width = frame.rect.width();
+            if width > 30.0 {
+                painter.text(
+                    frame.rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    frame_label(name, width),
+                    egui::FontId::proportional(11.0),
+                    egui::Color32::WHITE,
+                );
+            }
+        }
+
+        if response.clicked()
+            && let Some(frame) = hovered
+        {
+            zooms.insert(key, frame.path.clone());
+        }
+
+        if let Some(frame) = hovered {
+            response.on_hover_ui_at_pointer(|ui| Self::frame_tooltip(ui, frame, tree));
+        }
+    }
+
+    /// Places `node` and, where they are at least a pixel wide, its
+    /// descendants, from `left` on `row`.
+    fn layout<'n>(
+        node: &'n CallNode,
+        path: &mut Vec<String>,
+        left: f32,
+        row: usize,
+        scale: f32,
+        row_rect: &impl Fn(usize, f32, f32) -> egui::Rect,
+        frames: &mut Vec<FrameRect<'n>>,
+    ) {
+        let width = node.total as f32 * scale;
+        if width < 1.0 {
+            return;
+        }
+        path.push(node.name.clone());
+        frames.push(FrameRect {
+            rect: row_rect(row, left, width),
+            row,
+            path: path.clone(),
+            node,
+        });
+        let mut child_left = left;
+        for child in &node.children {
+            Self::layout(child, path, child_left, row + 1, scale, row_rect, frames);
+            child_left += child.total as f32 * scale;
+        }
+        path.pop();
+    }
+
+    fn frame_tooltip(ui: &mut egui::Ui, frame: &FrameRect, tree: &CallNode) {
+        let node = frame.node;
+        let share = node.total as f64 / tree.total.max(1) as f64 * 100.0;
+        if frame.path.is_empty() {
+            ui.label(egui::RichText::new("all").strong());
+        } else {
+            ui.label(egui::RichText::new(&node.name).strong());
+            ui.label(format!("Calls: {}", node.count));
+            ui.label(format!("Self: {}", format_duration(node.self_time())));
+        }
+        ui.label(format!(
+            "Total: {} ({:.1}%)",
+            format_duration(node.total),
+            share
+        ));
+        ui.label(egui::RichText::new("Click to zoom").weak());
+    }
+}
+
+fn sorted_threads(process: &Process) -> Vec<&Thread> {
+    let mut threads: Vec<&Thread> = process.threads.values().collect();
+    threads.sort_by_key(|thread| thread.id);
+    threads
+}
@@ -1,24 +1,63 @@
 use egui::{self, Vec2};
-use std::{collections::HashMap};
+use std::collections::HashMap;
 
-use crate::event::{Events, Thread};
+use crate::event::{CounterTrack, EventMark, EventSpan, Events, MarkScope, Process, Thread};
 
 pub const TIMELINE_MARK_INTERVAL: u64 = 1000000000;
 
+/// Identifies a collapsible group, to remember whether it is folded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupKey {
+    Process(u32),
+    Thread(u32, u64),
+    Tasks(u32),
+    Counters,
+    /// The call tree merging every thread.
+    AllThreads,
+}
+
 pub struct FlameGraphWidget<'a> {
     events: &'a Events,
-    folded_processes: &'a mut HashMap<usize, bool>,
+    folded_processes: &'a mut HashMap<GroupKey, bool>,
+    selected_span: &'a mut Option<EventSpan>,
+    /// Where each span with an id was drawn this frame, keyed by pid and
+    /// span id, for flow arrows.
+    span_rects: HashMap<(u32, u64), SpanRect>,
+}
+
+struct SpanRect {
+    rect: egui::Rect,
+    timestamp: u64,
+    duration: u64,
+}
+
+impl SpanRect {
+    /// Point on the span's centre line at `timestamp`.
+    fn point_at(&self, timestamp: u64) -> egui::Pos2 {
+        let offset = timestamp.saturating_sub(self.timestamp) as f32;
+        let fraction = (offset / self.duration.max(1) as f32).min(1.0);
+        egui::pos2(
+            self.rect.left() + fraction * self.rect.width(),
+            self.rect.center().y,
+        )
+    }
 }
 
 impl<'a> FlameGraphWidget<'a> {
-    pub fn new(events: &'a Events, folded_processes: &'a mut HashMap<usize, bool>) -> Self {
+    pub fn new(
+        events: &'a Events,
+        folded_processes: &'a mut HashMap<GroupKey, bool>,
+        selected_span: &'a mut Option<EventSpan>,
+    ) -> Self {
         Self {
             events,
             folded_processes,
+            selected_span,
+            span_rects: HashMap::new(),
         }
     }
 
-    pub fn show(self, ui: &mut egui::Ui) -> Option<egui::scroll_area::ScrollAreaOutput<()>> {
+    pub fn show(mut self, ui: &mut egui::Ui) -> Option<egui::scroll_area::ScrollAreaOutput<()>> {
         if self.events.is_empty() {
             ui.centered_and_justified(|ui| {
                 ui.label("No events loaded. Use File → Open to load a profile.");
@@ -27,6 +66,7 @@ impl<'a> FlameGraphWidget<'a> {
         }
 
         let (min_time, max_time) = self.get_global_time_range();
+        let events = self.events;
 
         // Create horizontal scroll area for main content
         let mut scroll_output = egui::ScrollArea::both()
@@ -42,52 +82,22 @@ impl<'a> FlameGraphWidget<'a> {
                 self.draw_grid_lines(ui, min_time, max_time, virtual_width);
                 ui.add_space(5.0);
 
-                let grouped_events = &self.events.threads;
-
-                let mut process_ids: Vec<_> = grouped_events.keys().copied().collect();
-
-                process_ids.sort();
-                for process_id in process_ids {
-                    
-                    if let Some(events) = grouped_events.get(&process_id) {
-                        let is_folded = self
-                            .folded_processes
-                            .get(&(process_id as usize))
-                            .copied()
-                            .unwrap_or(false);
-
-                        // Use frame without border
-                        egui::Frame::new()
-                            .inner_margin(egui::Margin::same(8))
-                            .show(ui, |ui| {
-                                ui.set_width(ui.available_width());
-
-                                // Header with fold/unfold button
-                                ui.horizontal(|ui| {
-                                    let button_text = if is_folded { "▶" } else { "▼" };
-                                    if ui.small_button(button_text).clicked() {
-                                        self.folded_processes.insert(process_id as usize, !is_folded);
-                                    }
-
-                                    ui.label(
-                                        egui::RichText::new(format!("Process {}", process_id))
-                                            .size(14.0)
-                                            .strong(),
-                                    );
-
-                                    let span_count = events.spans.len();
-                                    ui.label(format!("({} spans)", span_count));
-                                });
-
-                                // Show flamegraph if not folded
-                                if !is_folded {
-                                    ui.add_space(5.0);
-                                    self.draw_flamegraph(ui, events, min_time, max_time);
-                                }
-                            });
-
-                        ui.add_space(5.0);
-                    }
+                let mut processes: Vec<_> = events.processes.values().collect();
+                processes.sort_by_key(|process| process.pid);
+                for process in processes {
+                    self.draw_process_group(ui, process, min_time, max_time);
+                }
+
+                self.draw_flows(ui);
+
+                if !events.counters.is_empty() {
+                    let summary = format!("({} tracks)", events.counters.len());
+                    let title = "Counters".to_string();
+                    self.draw_group(ui, GroupKey::Counters, title, summary, |this, ui| {
+                        for track in &events.counters {
+                            this.draw_counter_track(ui, track, min_time, max_time);
+                        }
+                    });
                 }
                 ui.add_space(60.0);
             });
@@ -95,6 +105,175 @@ impl<'a> FlameGraphWidget<'a> {
         Some(scroll_output)
     }
 
+    fn draw_process_group(
+        &mut self,
+        ui: &mut egui::Ui,
+        process: &Process,
+        min_time: u64,
+        max_time: u64,
+    ) {
+        let pid = process.pid;
+        let summary = format!(
+            "({} threads, {} spans)",
+            process.threads.len(),
+            process.span_count()
+        );
+        let title = process.label();
+        self.draw_group(ui, GroupKey::Process(pid), title, summary, |this, ui| {
+            if !process.command_line.is_empty() {
+                ui.label(egui::RichText::new(process.command_line.join(" ")).weak());
+            }
+
+            let mut threads: Vec<_> = process.threads.values().collect();
+            threads.sort_by_key(|thread| thread.id);
+            for thread in threads {
+                let key = GroupKey::Thread(pid, thread.id);
+                this.draw_thread_group(ui, key, thread.label(), thread, min_time, max_time);
+            }
+
+            if !process.tasks.is_empty() {
+                let title = "Async tasks".to_string();
+                let key = GroupKey::Tasks(pid);
+                this.draw_thread_group(ui, key, title, &process.tasks, min_time, max_time);
+            }
+        });
+    }
+
+    fn draw_thread_group(
+        &mut self,
+        ui: &mut egui::Ui,
+        fold_key: GroupKey,
+        title: String,
+        events: &Thread,
+        min_time: u64,
+        max_time: u64,
+    ) {
+        let summary = format!("({} spans)", events.spans.len());
+        self.draw_group(ui, fold_key, title, summary, |this, ui| {
+            this.draw_flamegraph(ui, events, min_time, max_time);
+        });
+    }
+
+    fn draw_group(
+        &mut self,
+        ui: &mut egui::Ui,
+        fold_key: GroupKey,
+        title: String,
+        summary: String,
+        contents: impl FnOnce(&mut Self, &mut egui::Ui),
+    ) {
+        let is_folded = self
+            .folded_processes
+            .get(&fold_key)
+            .copied()
+            .unwrap_or(false);
+
+        // Use frame without border
+        egui::Frame::new()
+            .inner_margin(egui::Margin::same(8))
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+
+                // Header with fold/unfold button
+                ui.horizontal(|ui| {
+                    let button_text = if is_folded { "▶" } else { "▼" };
+                    if ui.small_button(button_text).clicked() {
+                        self.folded_processes.insert(fold_key, !is_folded);
+                    }
+
+                    ui.label(egui::RichText::new(title).size(14.0).strong());
+                    ui.label(summary);
+                });
+
+                // Show contents if not folded
+                if !is_folded {
+                    ui.add_space(5.0);
+                    contents(self, ui);
+                }
+            });
+
+        ui.add_space(5.0);
+    }
+
+    /// Draws a counter as a stepped area chart, holding each sample's value
+    /// until the next one.
+    fn draw_counter_track(
+        &mut self,
+        ui: &mut egui::Ui,
+        track: &CounterTrack,
+        min_time: u64,
+        max_time: u64,
+    ) {
+        let (low, high) = track.range();
+        let name = if self.events.processes.len() > 1 {
+            format!("{} ({})", track.name, track.pid)
+        } else {
+            track.name.clone()
+        };
+        ui.label(format!("{}  [{} – {}]", name, low, high));
+
+        let track_height = 40.0;
+        let (response, painter) = ui.allocate_painter(
+            egui::Vec2::new(ui.available_width(), track_height),
+            egui::Sense::hover(),
+        );
+        let rect = response.rect;
+        let time_range = (max_time - min_time) as f32;
+        let x_at = |timestamp: u64| {
+            rect.left() + ((timestamp - min_time) as f32 / time_range) * rect.width()
+        };
+        // Leave the top row of pixels free so the maximum stays visible.
+        let value_range = if high > low { high - low } else { 1.0 };
+        let y_at = |value: f64| {
+            rect.bottom() - ((value - low) / value_range) as f32 * (rect.height() - 2.0)
+        };
+
+        let color = egui::Color32::from_rgb(66, 165, 245);
+        let fill = color.gamma_multiply(0.35);
+        painter.rect_filled(
+            rect,
+            egui::CornerRadius::same(2),
+            egui::Color32::from_gray(30),
+        );
+
+        let end_time = self.events.total_duration;
+        for (index, sample) in track.samples.iter().enumerate() {
+            let next_time = track
+                .samples
+                .get(index + 1)
+                .map_or(end_time, |next| next.timestamp);
+            let (x_start, x_end) = (x_at(sample.timestamp), x_at(next_time));
+            let y = y_at(sample.value);
+
+            painter.rect_filled(
+                egui::Rect::from_min_max(egui::pos2(x_start, y), egui::pos2(x_end, rect.bottom())),
+                egui::CornerRadius::ZERO,
+                fill,
+            );
+            painter.line_segment(
+                [egui::pos2(x_start, y), egui::pos2(x_end, y)],
+                egui::Stroke::new(1.5_f32, color),
+            );
+            if let Some(next) = track.samples.get(index + 1) {
+                painter.line_segment(
+                    [egui::pos2(x_end, y), egui::pos2(x_end, y_at(next.value))],
+                    egui::Stroke::new(1.5_f32, color),
+                );
+            }
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let timestamp = min_time + ((pos.x - rect.left()) / rect.width() * time_range) as u64;
+            if let Some(sample) = track.value_at(timestamp) {
+                response.on_hover_ui_at_pointer(|ui| {
+                    ui.label(egui::RichText::new(&track.name).strong());
+                    ui.label(format!("Value: {}", sample.value));
+                    ui.label(format!("Since: {}", format_duration(sample.timestamp)));
+                });
+            }
+        }
+    }
+
     pub fn draw_timeline_axis(&self, ui: &mut egui::Ui) {
         let (min_time, max_time) = self.get_global_time_range();
         self.draw_time_axis(ui, min_time, max_time);
@@ -123,7 +302,7 @@ impl<'a> FlameGraphWidget<'a> {
                     egui::pos2(x, content_rect.top()),
                     egui::pos2(x, content_rect.bottom()),
                 ],
-                egui::Stroke::new(0.5, egui::Color32::from_gray(50)),
+                egui::Stroke::new(0.5_f32, egui::Color32::from_gray(50)),
             );
 
             // Draw minor grid lines
@@ -141,7 +320,7 @@ impl<'a> FlameGraphWidget<'a> {
                                 egui::pos2(minor_x, content_rect.top()),
                                 egui::pos2(minor_x, content_rect.bottom()),
                             ],
-                            egui::Stroke::new(0.3, egui::Color32::from_gray(40)),
+                            egui::Stroke::new(0.3_f32, egui::Color32::from_gray(40)),
                         );
                     }
                 }
@@ -169,7 +348,7 @@ impl<'a> FlameGraphWidget<'a> {
                 egui::pos2(rect.left(), rect.top() + 10.0),
                 egui::pos2(rect.right(), rect.top() + 10.0),
             ],
-            egui::Stroke::new(1.0, egui::Color32::GRAY),
+            egui::Stroke::new(1.0_f32, egui::Color32::GRAY),
         );
 
         // Calculate the first mark position
@@ -187,7 +366,7 @@ impl<'a> FlameGraphWidget<'a> {
                     egui::pos2(x, rect.top() + 5.0),
                     egui::pos2(x, rect.top() + 15.0),
                 ],
-                egui::Stroke::new(2.0, egui::Color32::GRAY),
+                egui::Stroke::new(2.0_f32, egui::Color32::GRAY),
             );
 
             // Draw label
@@ -214,7 +393,7 @@ impl<'a> FlameGraphWidget<'a> {
                                 egui::pos2(minor_x, rect.top() + 8.0),
                                 egui::pos2(minor_x, rect.top() + 12.0),
                             ],
-                            egui::Stroke::new(1.0, egui::Color32::from_gray(100)),
+                            egui::Stroke::new(1.0_f32, egui::Color32::from_gray(100)),
                         );
                     }
                 }
@@ -224,7 +403,7 @@ impl<'a> FlameGraphWidget<'a> {
         }
 
         // Draw start and end markers
-        if min_time % TIMELINE_MARK_INTERVAL != 0 {
+        if !min_time.is_multiple_of(TIMELINE_MARK_INTERVAL) {
             painter.text(
                 egui::pos2(rect.left(), rect.top() + 25.0),
                 egui::Align2::LEFT_CENTER,
@@ -234,7 +413,7 @@ impl<'a> FlameGraphWidget<'a> {
             );
         }
 
-        if max_time % TIMELINE_MARK_INTERVAL != 0 {
+        if !max_time.is_multiple_of(TIMELINE_MARK_INTERVAL) {
             painter.text(
                 egui::pos2(rect.right(), rect.top() + 25.0),
                 egui::Align2::RIGHT_CENTER,
@@ -245,9 +424,15 @@ impl<'a> FlameGraphWidget<'a> {
         }
     }
 
-    fn draw_flamegraph(&self, ui: &mut egui::Ui, spans: &Thread, min_time: u64, max_time: u64) {
+    fn draw_flamegraph(&mut self, ui: &mut egui::Ui, spans: &Thread, min_time: u64, max_time: u64) {
+        let events = self.events;
+        let marks: Vec<&EventMark> = events
+            .marks
+            .iter()
+            .filter(|mark| mark.applies_to(spans.pid, spans.id))
+            .collect();
 
-        if spans.is_empty() {
+        if spans.is_empty() && marks.is_empty() {
             ui.label("No complete event spans to display");
             return;
         }
@@ -264,10 +449,12 @@ impl<'a> FlameGraphWidget<'a> {
         // Draw the flamegraph
         let (response, painter) = ui.allocate_painter(
             egui::Vec2::new(available_width, graph_height),
-            egui::Sense::hover(),
+            egui::Sense::click(),
         );
 
         let rect = response.rect;
+        let hover_pos = response.hover_pos();
+        let mut hovered = None;
 
         // Draw spans
         for span in &spans.spans {
@@ -284,47 +471,192 @@ impl<'a> FlameGraphWidget<'a> {
                 egui::Vec2::new(width, block_height),
             );
 
-            // Generate color based on depth and message hash
-            let hash = span
-                .name
-                .bytes()
-                .fold(0u32, |acc, b| acc.wrapping_add(b as u32));
-            let color = match (span.depth + hash as u64) % 6 {
-                0 => egui::Color32::from_rgb(66, 165, 245),  // Blue
-                1 => egui::Color32::from_rgb(102, 187, 106), // Green
-                2 => egui::Color32::from_rgb(255, 167, 38),  // Orange
-                3 => egui::Color32::from_rgb(239, 83, 80),   // Red
-                4 => egui::Color32::from_rgb(156, 39, 176),  // Purple
-                _ => egui::Color32::from_rgb(0, 188, 212),   // Cyan
-            };
+            let color = Self::block_color(span.category.as_deref(), &span.name, span.depth);
+
+            if hover_pos.is_some_and(|pos| block_rect.contains(pos)) {
+                hovered = Some(span);
+            }
 
             // Draw block
             painter.rect_filled(block_rect, egui::CornerRadius::same(2), color);
+            if span.span_id != 0 {
+                self.span_rects.insert(
+                    (span.pid, span.span_id),
+                    SpanRect {
+                        rect: block_rect,
+                        timestamp: span.timestamp,
+                        duration: span.duration,
+                    },
+                );
+            }
 
-            // Draw border
+            // Draw border, highlighted for the selected span
+            let border = if self.selected_span.as_ref() == Some(span) {
+                egui::Stroke::new(2.0_f32, egui::Color32::WHITE)
+            } else {
+                egui::Stroke::new(1.0_f32, egui::Color32::from_gray(60))
+            };
             painter.rect_stroke(
                 block_rect,
                 egui::CornerRadius::same(2),
-                egui::Stroke::new(1.0, egui::Color32::from_gray(60)),
+                border,
                 egui::StrokeKind::Inside,
             );
 
             // Draw text if there's enough space
             if width > 30.0 {
-                let text = if span.name.len() > 20 && width < 150.0 {
-                    format!("{}...", &span.name[..17])
-                } else {
-                    span.name.clone()
-                };
-
                 painter.text(
                     block_rect.center(),
                     egui::Align2::CENTER_CENTER,
-                    text,
+                    frame_label(&span.name, width),
                     egui::FontId::proportional(11.0),
                     egui::Color32::WHITE,
                 );
             }
         }
+
+        // Draw instant events over the spans
+        let mut hovered_mark = None;
+        for mark in marks {
+            let x = rect.left() + ((mark.timestamp - min_time) as f32 / time_range) * rect.width();
+            let color = Self::mark_color(mark.scope);
+            painter.line_segment(
+                [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
+                egui::Stroke::new(1.0_f32, color),
+            );
+            if mark.scope == MarkScope::Thread {
+                painter.add(egui::Shape::convex_polygon(
+                    vec![
+                        egui::pos2(x - 5.0, rect.top()),
+                        egui::pos2(x + 5.0, rect.top()),
+                        egui::pos2(x, rect.top() + 8.0),
+                    ],
+                    color,
+                    egui::Stroke::NONE,
+                ));
+            }
+
+            if hover_pos.is_some_and(|pos| (pos.x - x).abs() <= 4.0) {
+                hovered_mark = Some(mark);
+            }
+        }
+
+        if response.clicked() {
+            *self.selected_span = hovered.cloned();
+        }
+
+        if let Some(mark) = hovered_mark {
+            response.on_hover_ui_at_pointer(|ui| Self::mark_tooltip(ui, mark));
+        } else if let Some(span) = hovered {
+            response.on_hover_ui_at_pointer(|ui| Self::span_tooltip(ui, span));
+        }
+    }
+
+    /// Draws an arrow for every flow whose spans are both on screen, which
+    /// they aren't when their thread or process is folded.
+    fn draw_flows(&self, ui: &mut egui::Ui) {
+        let painter = ui.painter();
+        let stroke = egui::Stroke::new(1.5_f32, egui::Color32::from_gray(220));
+
+        for flow in &self.events.flows {
+            let (Some(begin), Some(end)) = (
+                self.span_rects.get(&(flow.begin.pid, flow.begin.span_id)),
+                self.span_rects.get(&(flow.end.pid, flow.end.span_id)),
+            ) else {
+                continue;
+            };
+            let from = begin.point_at(flow.begin.timestamp);
+            let to = end.point_at(flow.end.timestamp);
+            painter.circle_filled(from, 3.0, stroke.color);
+            painter.arrow(from, to - from, stroke);
+        }
+    }
+
+    fn mark_color(scope: MarkScope) -> egui::Color32 {
+        match scope {
+            MarkScope::Thread => egui::Color32::from_rgb(255, 235, 59), // Yellow
+            MarkScope::Process => egui::Color32::from_rgb(255, 112, 67), // Deep orange
+            MarkScope::Global => egui::Color32::from_rgb(236, 64, 122), // Pink
+        }
+    }
+
+    fn mark_tooltip(ui: &mut egui::Ui, mark: &EventMark) {
+        ui.label(egui::RichText::new(&mark.name).strong());
+        ui.label(format!("At: {}", format_duration(mark.timestamp)));
+        ui.label(format!("Scope: {:?}", mark.scope));
+        for arg in &mark.args {
+            ui.label(format!("{}: {}", arg.key, arg.value));
+        }
+    }
+
+    /// Spans of a category share its colour, others vary by depth and name.
+    pub fn block_color(category: Option<&str>, name: &str, depth: u64) -> egui::Color32 {
+        let color_key = match category {
+            Some(category) => Self::text_hash(category) as u64,
+            None => depth + Self::text_hash(name) as u64,
+        };
+        match color_key % 6 {
+            0 => egui::Color32::from_rgb(66, 165, 245),  // Blue
+            1 => egui::Color32::from_rgb(102, 187, 106), // Green
+            2 => egui::Color32::from_rgb(255, 167, 38),  // Orange
+            3 => egui::Color32::from_rgb(239, 83, 80),   // Red
+            4 => egui::Color32::from_rgb(156, 39, 176),  // Purple
+            _ => egui::Color32::from_rgb(0, 188, 212),   // Cyan
+        }
+    }
+
+    fn text_hash(text: &str) -> u32 {
+        text.bytes().fold(0u32, |acc, b| acc.wrapping_add(b as u32))
+    }
+
+    fn span_tooltip(ui: &mut egui::Ui, span: &EventSpan) {
+        ui.label(egui::RichText::new(&span.name).strong());
+        ui.label(format!("Duration: {}", format_duration(span.duration)));
+        ui.label(format!("Start: {}", format_duration(span.timestamp)));
+        if let Some(category) = &span.category {
+            ui.label(format!("Category: {}", category));
+        }
+        if let Some(location) = &span.location {
+            ui.label(format!("Module: {}", location.module_path));
+            ui.label(format!("Source: {}:{}", location.file, location.line));
+        }
+        if span.task_id != 0 {
+            ui.label(format!("Task: {}", span.task_id));
+        }
+    }
+}
+
+/// Formats nanoseconds with a unit that keeps the number readable.
+pub fn format_duration(nanos: u64) -> String {
+    match nanos {
+        0..1_000 => format!("{} ns", nanos),
+        1_000..1_000_000 => format!("{:.2} µs", nanos as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.2} ms", nanos as f64 / 1e6),
+        _ => format!("{:.2} s", nanos as f64 / 1e9),
+    }
+}
+
+/// Text drawn on a frame `width` points wide, cutting long names short on
+/// narrow frames. Names from imported traces can be any text, so this counts
+/// chars rather than bytes.
+pub fn frame_label(name: &str, width: f32) -> String {
+    if name.chars().count() > 20 && width < 150.0 {
+        format!("{}...", name.chars().take(17).collect::<String>())
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_names_are_cut_at_a_char_boundary() {
+        // Byte 17 falls inside `レ`.
+        let name = "x描画処理::レイアウト計算::テキスト折り返し";
+        assert_eq!(frame_label(name, 100.0), "x描画処理::レイアウト計算::テ...");
+        assert_eq!(frame_label(name, 200.0), name);
+        assert_eq!(frame_label("short", 100.0), "short");
     }
 }
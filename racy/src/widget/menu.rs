@@ -7,18 +7,42 @@ pub enum MenuAction {
     OpenFile,
     SaveFile,
     SaveFileAs,
+    ExportChromeTrace,
     Exit,
     ExpandAll,
     CollapseAll,
+    SetViewMode(ViewMode),
+    ToggleMergeThreads,
+}
+
+/// How the main panel shows the loaded spans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    /// Spans of each thread where they ran in time.
+    Timeline,
+    /// Spans merged by call path, sized by the time spent in them.
+    CallTree,
+    /// Span names by self time, each expanding into its callers.
+    BottomUp,
 }
 
 pub struct MenuBar<'a> {
     current_file: &'a Option<PathBuf>,
+    view_mode: ViewMode,
+    merge_threads: bool,
 }
 
 impl<'a> MenuBar<'a> {
-    pub fn new(current_file: &'a Option<PathBuf>) -> Self {
-        Self { current_file }
+    pub fn new(
+        current_file: &'a Option<PathBuf>,
+        view_mode: ViewMode,
+        merge_threads: bool,
+    ) -> Self {
+        Self {
+            current_file,
+            view_mode,
+            merge_threads,
+        }
     }
 
     pub fn show(&self, ctx: &egui::Context) -> MenuAction {
@@ -50,6 +74,13 @@ impl<'a> MenuBar<'a> {
                         ui.close();
                     }
 
+                    ui.menu_button("Export", |ui| {
+                        if ui.button("Chrome Trace...").clicked() {
+                            action = MenuAction::ExportChromeTrace;
+                            ui.close();
+                        }
+                    });
+
                     ui.separator();
 
                     if ui.button("Exit").clicked() {
@@ -60,6 +91,30 @@ impl<'a> MenuBar<'a> {
 
                 // View menu
                 ui.menu_button("View", |ui| {
+                    for (mode, label) in [
+                        (ViewMode::Timeline, "Timeline"),
+                        (ViewMode::CallTree, "Call Tree"),
+                        (ViewMode::BottomUp, "Bottom-Up"),
+                    ] {
+                        if ui.radio(self.view_mode == mode, label).clicked() {
+                            action = MenuAction::SetViewMode(mode);
+                            ui.close();
+                        }
+                    }
+
+                    // The timeline always keeps threads apart
+                    let mut merge_threads = self.merge_threads;
+                    let checkbox = egui::Checkbox::new(&mut merge_threads, "Merge Threads");
+                    if ui
+                        .add_enabled(self.view_mode != ViewMode::Timeline, checkbox)
+                        .clicked()
+                    {
+                        action = MenuAction::ToggleMergeThreads;
+                        ui.close();
+                    }
+
+                    ui.separator();
+
                     if ui.button("Expand All").clicked() {
                         action = MenuAction::ExpandAll;
                         ui.close();
@@ -73,10 +128,10 @@ impl<'a> MenuBar<'a> {
 
                 // Show current file in menu bar
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    if let Some(path) = self.current_file {
-                        if let Some(filename) = path.file_name() {
-                            ui.label(format!("File: {}", filename.to_string_lossy()));
-                        }
+                    if let Some(path) = self.current_file
+                        && let Some(filename) = path.file_name()
+                    {
+                        ui.label(format!("File: {}", filename.to_string_lossy()));
                     }
                 });
             });
@@ -90,6 +145,8 @@ impl<'a> MenuBar<'a> {
 pub fn open_file_dialog() -> Option<PathBuf> {
     rfd::FileDialog::new()
         .add_filter("JSON files", &["json"])
+        .add_filter("Racy traces", &["bin"])
+        .add_filter("Chrome traces", &["json"])
         .add_filter("All files", &["*"])
         .pick_file()
 }
@@ -100,3 +157,10 @@ pub fn save_file_dialog() -> Option<PathBuf> {
         .set_file_name("flamegraph.json")
         .save_file()
 }
+
+pub fn export_chrome_trace_dialog() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter("Chrome traces", &["json"])
+        .set_file_name("trace.json")
+        .save_file()
+}
@@ -1,2 +1,4 @@
+pub mod call_tree;
 pub mod flame_graph;
 pub mod menu;
+pub mod span_details;
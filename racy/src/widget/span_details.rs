@@ -0,0 +1,107 @@
+use egui;
+
+use crate::{event::EventSpan, widget::flame_graph::format_duration};
+
+/// Side panel describing the span selected in the flame graph.
+pub struct SpanDetailsPanel<'a> {
+    selected_span: &'a mut Option<EventSpan>,
+}
+
+impl<'a> SpanDetailsPanel<'a> {
+    pub fn new(selected_span: &'a mut Option<EventSpan>) -> Self {
+        Self { selected_span }
+    }
+
+    pub fn show(self, ctx: &egui::Context) {
+        let Some(span) = self.selected_span.as_ref() else {
+            return;
+        };
+        let mut close = false;
+
+        egui::SidePanel::right("span_details")
+            .resizable(true)
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(&span.name);
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        close = ui.small_button("✕").clicked();
+                    });
+                });
+                ui.separator();
+
+                egui::Grid::new("span_summary")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Duration");
+                        ui.label(format_duration(span.duration));
+                        ui.end_row();
+
+                        ui.label("Start");
+                        ui.label(format_duration(span.timestamp));
+                        ui.end_row();
+
+                        if span.pid != 0 {
+                            ui.label("Process");
+                            ui.label(span.pid.to_string());
+                            ui.end_row();
+                        }
+
+                        ui.label("Thread");
+                        ui.label(span.id.to_string());
+                        ui.end_row();
+
+                        if let Some(category) = &span.category {
+                            ui.label("Category");
+                            ui.label(category);
+                            ui.end_row();
+                        }
+
+                        if span.task_id != 0 {
+                            ui.label("Task");
+                            ui.label(span.task_id.to_string());
+                            ui.end_row();
+                        }
+
+                        if let Some(location) = &span.location {
+                            ui.label("Module");
+                            ui.label(&location.module_path);
+                            ui.end_row();
+
+                            ui.label("Source");
+                            ui.label(format!("{}:{}", location.file, location.line));
+                            ui.end_row();
+                        }
+                    });
+
+                ui.add_space(10.0);
+                ui.label(egui::RichText::new("Arguments").strong());
+
+                if span.args.is_empty() {
+                    ui.label("No arguments recorded");
+                    return;
+                }
+
+                egui::Grid::new("span_args")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label(egui::RichText::new("Key").strong());
+                        ui.label(egui::RichText::new("Value").strong());
+                        ui.label(egui::RichText::new("Type").strong());
+                        ui.end_row();
+
+                        for arg in &span.args {
+                            ui.label(&arg.key);
+                            ui.label(arg.value.to_string());
+                            ui.label(arg.value.type_name());
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if close {
+            *self.selected_span = None;
+        }
+    }
+}